@@ -0,0 +1,236 @@
+use crate::storage::{Record, Store};
+use crate::utils::preview;
+use crate::{Context, Data};
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use log::warn;
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::process::ExitStatus;
+use std::time::Instant;
+
+/// Longest arguments shown in the log channel
+const MIRROR_ARGUMENTS_LENGTH: usize = 1500;
+
+/// A single invocation of a privileged command
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuditEntry {
+    pub user: UserId,
+    pub guild: Option<GuildId>,
+    pub channel: ChannelId,
+    /// Full name of the command, like `commands rollback`
+    pub command: String,
+    /// The command as it was invoked, including all arguments
+    pub arguments: String,
+    pub status: AuditStatus,
+    /// Time the command took to run, unknown if it didn't run
+    pub duration_ms: Option<u64>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Record for AuditEntry {
+    const PREFIX: &'static str = "audit";
+    const VERSION: u32 = 1;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum AuditStatus {
+    Success,
+    /// A process run by the command failed, with its exit code if it wasn't killed
+    Failed {
+        exit_code: Option<i32>,
+    },
+    /// The command returned an error
+    Error {
+        message: String,
+    },
+    /// The user lacked the permissions to run the command
+    Denied,
+}
+
+impl Display for AuditStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditStatus::Success => write!(f, "success"),
+            AuditStatus::Failed {
+                exit_code: Some(code),
+            } => write!(f, "failed with exit code {}", code),
+            AuditStatus::Failed { exit_code: None } => write!(f, "failed, process didn't exit"),
+            AuditStatus::Error { message } => write!(f, "error: {}", message),
+            AuditStatus::Denied => write!(f, "denied"),
+        }
+    }
+}
+
+/// State of a running privileged command, kept in the invocation data
+struct Pending {
+    started: Instant,
+    failure: Option<AuditStatus>,
+}
+
+/// Commands with a permission check are privileged and get audited
+fn is_privileged(ctx: Context<'_>) -> bool {
+    let command = ctx.command();
+    command.owners_only || !command.checks.is_empty()
+}
+
+/// Start timing a command, called before every command runs
+pub async fn start(ctx: Context<'_>) {
+    if is_privileged(ctx) {
+        ctx.set_invocation_data(Pending {
+            started: Instant::now(),
+            failure: None,
+        })
+        .await;
+    }
+}
+
+/// Note the result of a process run by a command, so failures show up in the audit log.
+/// `None` means the process couldn't be started.
+pub async fn process_exited(ctx: Context<'_>, status: Option<ExitStatus>) {
+    if status.is_some_and(|status| status.success()) {
+        return;
+    }
+
+    if let Some(mut pending) = ctx.invocation_data::<Pending>().await {
+        // the first failure is the interesting one
+        pending.failure.get_or_insert(AuditStatus::Failed {
+            exit_code: status.and_then(|status| status.code()),
+        });
+    }
+}
+
+/// Record a command that ran to completion
+pub async fn finish(ctx: Context<'_>) {
+    let pending = ctx
+        .invocation_data::<Pending>()
+        .await
+        .map(|pending| (pending.started, pending.failure.clone()));
+
+    if let Some((started, failure)) = pending {
+        record(ctx, failure.unwrap_or(AuditStatus::Success), Some(started)).await;
+    }
+}
+
+/// Record privileged commands that failed or were denied
+pub async fn on_error(error: &poise::FrameworkError<'_, Data, Error>) {
+    match error {
+        poise::FrameworkError::Command { error, ctx } => {
+            let started = ctx
+                .invocation_data::<Pending>()
+                .await
+                .map(|pending| pending.started);
+
+            if started.is_some() {
+                let status = AuditStatus::Error {
+                    message: error.to_string(),
+                };
+                record(*ctx, status, started).await;
+            }
+        }
+        poise::FrameworkError::CommandCheckFailed { ctx, .. }
+        | poise::FrameworkError::NotAnOwner { ctx }
+            if is_privileged(*ctx) =>
+        {
+            record(*ctx, AuditStatus::Denied, None).await;
+        }
+        _ => (),
+    }
+}
+
+async fn record(ctx: Context<'_>, status: AuditStatus, started: Option<Instant>) {
+    let entry = AuditEntry {
+        user: ctx.author().id,
+        guild: ctx.guild_id(),
+        channel: ctx.channel_id(),
+        command: ctx.command().qualified_name.clone(),
+        arguments: ctx.invocation_string(),
+        status,
+        duration_ms: started.map(|started| started.elapsed().as_millis() as u64),
+        timestamp: Utc::now(),
+    };
+
+    if let Err(e) = save(ctx.data(), &entry) {
+        warn!("Error saving audit entry: {:?}", e);
+    }
+
+    if let Err(e) = mirror(ctx, &entry).await {
+        warn!("Error sending audit entry to the log channel: {:?}", e);
+    }
+}
+
+fn save(data: &Data, entry: &AuditEntry) -> Result<(), Error> {
+    // ids are increasing, so entries of a guild are ordered by the time they were saved
+    let id = format!(
+        "{}{:020}",
+        guild_prefix(entry.guild),
+        data.db.generate_id()?
+    );
+    data.audit()?.insert(&id, entry)?;
+
+    Ok(())
+}
+
+/// Start of the keys of entries of a guild, or of direct messages
+fn guild_prefix(guild: Option<GuildId>) -> String {
+    match guild {
+        Some(guild_id) => format!("{}/", guild_id.0),
+        None => "dm/".to_string(),
+    }
+}
+
+/// Send an entry to the log channel of its guild, if one is set
+async fn mirror(ctx: Context<'_>, entry: &AuditEntry) -> Result<(), Error> {
+    let guild_id = match entry.guild {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let channel = match ctx.data().guild(guild_id)?.settings()?.audit_channel {
+        Some(channel) => channel,
+        None => return Ok(()),
+    };
+
+    channel
+        .send_message(ctx, |m| {
+            m.content(format!(
+                "{}\n```\n{}```",
+                describe(entry),
+                preview(&entry.arguments, MIRROR_ARGUMENTS_LENGTH).replace("```", "`\u{200b}``")
+            ))
+            .allowed_mentions(|a| a.empty_parse())
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// One line summary of an entry
+pub fn describe(entry: &AuditEntry) -> String {
+    let duration = match entry.duration_ms {
+        Some(ms) => format!(" in {} ms", ms),
+        None => String::new(),
+    };
+
+    format!(
+        "<t:{}:f> <@{}> ran `{}` in <#{}>: {}{}",
+        entry.timestamp.timestamp(),
+        entry.user,
+        entry.command,
+        entry.channel,
+        entry.status,
+        duration
+    )
+}
+
+/// Audit entries of a guild, or of direct messages, newest first
+pub fn entries(
+    store: &Store,
+    guild: Option<GuildId>,
+) -> impl Iterator<Item = Result<AuditEntry, Error>> {
+    store
+        .scan::<AuditEntry>(&guild_prefix(guild))
+        .rev()
+        .map(|entry| entry.map(|(_, entry)| entry))
+}
@@ -1,5 +1,10 @@
+pub mod audit;
+pub mod config;
 pub mod custom;
 pub mod dev;
 pub mod fun;
 pub mod git;
 pub mod packwiz;
+pub mod perm;
+pub mod responder;
+pub mod variable;
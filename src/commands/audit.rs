@@ -0,0 +1,177 @@
+use super::custom::ENTRIES_PER_PAGE;
+use crate::audit::{describe, entries};
+use crate::permissions::{audit_view, perms_manage};
+use crate::utils::{guild_store, preview};
+use crate::{say, Context};
+use anyhow::{bail, Error};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use poise::serenity_prelude::{GuildChannel, Mentionable, User};
+
+/// Longest arguments shown in search results
+const ARGUMENTS_LENGTH: usize = 100;
+/// Searches stop after finding this many entries
+const MAX_RESULTS: usize = 500;
+
+/// Search the log of privileged commands
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("search", "channel")
+)]
+pub async fn audit(ctx: Context<'_>) -> Result<(), Error> {
+    say!(
+        ctx,
+        "Use `audit search` to search the log, or `audit channel` to set the log channel"
+    );
+    Ok(())
+}
+
+/// Searches the audit log, newest entries first.
+///
+/// At most 500 entries are shown, narrow the search to find older ones.
+/// Times are either relative like `30m`, `2h`, `7d` and `2w`, or dates like `2026-01-31`.
+#[poise::command(slash_command, prefix_command, guild_only, check = "audit_view")]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "Only commands run by this user"] user: Option<User>,
+    #[description = "Only this command, like packwiz or commands rollback"] command: Option<String>,
+    #[description = "Only entries after this time, like 2h or 2026-01-31"] since: Option<String>,
+    #[description = "Only entries before this time, like 2h or 2026-01-31"] until: Option<String>,
+) -> Result<(), Error> {
+    let (since, until) = match (parse_time(since), parse_time(until)) {
+        (Ok(since), Ok(until)) => (since, until),
+        (Err(e), _) | (_, Err(e)) => {
+            say!(ctx, "{}", e);
+            return Ok(());
+        }
+    };
+
+    let command = command.map(|command| command.trim().to_lowercase());
+
+    let mut results = Vec::new();
+
+    for entry in entries(&ctx.data().audit()?, ctx.guild_id()) {
+        let entry = entry?;
+
+        let matches = user.as_ref().is_none_or(|user| entry.user == user.id)
+            && command.as_ref().is_none_or(|command| {
+                entry.command == *command || entry.command.starts_with(&format!("{} ", command))
+            })
+            && since.is_none_or(|since| entry.timestamp >= since)
+            && until.is_none_or(|until| entry.timestamp <= until);
+
+        if matches {
+            results.push(format!(
+                "{}\n> `{}`",
+                describe(&entry),
+                preview(&entry.arguments, ARGUMENTS_LENGTH).replace('`', "'")
+            ));
+
+            if results.len() >= MAX_RESULTS {
+                break;
+            }
+        }
+    }
+
+    if results.is_empty() {
+        say!(ctx, "No audit entries found");
+        return Ok(());
+    }
+
+    let pages: Vec<String> = results
+        .chunks(ENTRIES_PER_PAGE)
+        .map(|page| page.join("\n"))
+        .collect();
+    let pages: Vec<&str> = pages.iter().map(String::as_str).collect();
+
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
+
+/// Sets the channel audit entries are sent to.
+///
+/// Without a channel, entries are no longer sent anywhere.
+#[poise::command(slash_command, prefix_command, guild_only, check = "perms_manage")]
+pub async fn channel(
+    ctx: Context<'_>,
+    #[description = "Log channel"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let mut settings = store.settings()?;
+    settings.audit_channel = channel.as_ref().map(|channel| channel.id);
+    store.set_settings(&settings)?;
+
+    match channel {
+        Some(channel) => say!(ctx, "Audit entries are sent to {}", channel.mention()),
+        None => say!(ctx, "Audit entries are no longer sent to a channel"),
+    }
+
+    Ok(())
+}
+
+/// Units of relative times with their length in minutes
+const UNITS: [(char, i64); 4] = [('m', 1), ('h', 60), ('d', 60 * 24), ('w', 60 * 24 * 7)];
+
+/// Parse a relative time like `2h` or a date like `2026-01-31`
+fn parse_time(time: Option<String>) -> Result<Option<DateTime<Utc>>, Error> {
+    let time = match time {
+        Some(time) => time.trim().to_lowercase(),
+        None => return Ok(None),
+    };
+
+    if let Ok(date) = NaiveDate::parse_from_str(&time, "%Y-%m-%d") {
+        if let Some(start) = date.and_hms_opt(0, 0, 0) {
+            return Ok(Some(DateTime::from_naive_utc_and_offset(start, Utc)));
+        }
+    }
+
+    let ago = UNITS.iter().find_map(|(unit, minutes)| {
+        let amount = time.strip_suffix(*unit)?.parse::<u32>().ok()?;
+        Some(Duration::minutes(i64::from(amount) * minutes))
+    });
+
+    match ago.and_then(|ago| Utc::now().checked_sub_signed(ago)) {
+        Some(time) => Ok(Some(time)),
+        None => bail!(
+            "`{}` is not a time like 30m, 2h, 7d, 2w or 2026-01-31",
+            time
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(time: &str) -> Result<Option<DateTime<Utc>>, Error> {
+        parse_time(Some(time.to_string()))
+    }
+
+    #[test]
+    fn relative_times() {
+        let ago = Utc::now() - parse("2H").unwrap().unwrap();
+
+        // the wall clock may jump a little between both calls
+        assert!((ago - Duration::hours(2)).num_seconds().abs() < 60);
+    }
+
+    #[test]
+    fn dates() {
+        let time = parse("2026-01-31").unwrap().unwrap();
+
+        assert_eq!(time.to_rfc3339(), "2026-01-31T00:00:00+00:00");
+    }
+
+    #[test]
+    fn invalid_times() {
+        for time in ["", " ", "m", "-2h", "2x", "é", "99999999999w"] {
+            assert!(parse(time).is_err(), "`{}` was accepted", time);
+        }
+
+        assert!(parse("4294967295w").is_err());
+        assert!(parse_time(None).unwrap().is_none());
+    }
+}
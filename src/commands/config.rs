@@ -0,0 +1,138 @@
+use crate::config::Config;
+use crate::permissions::owner;
+use crate::utils::guild_store;
+use crate::{say, Context};
+use anyhow::Error;
+use log::info;
+use poise::serenity_prelude::Mentionable;
+use std::sync::Arc;
+
+/// Longest prefix a guild can set
+const MAX_PREFIX_LENGTH: usize = 10;
+
+/// Inspect and reload the configuration of the bot
+#[poise::command(slash_command, prefix_command, subcommands("show", "reload", "prefix"))]
+pub async fn config(ctx: Context<'_>) -> Result<(), Error> {
+    say!(
+        ctx,
+        "Use `config show` to see the configuration, `config reload` to reload it, \
+         or `config prefix` to change the prefix of this server"
+    );
+    Ok(())
+}
+
+/// Shows the current configuration
+#[poise::command(slash_command, prefix_command, check = "owner", ephemeral)]
+pub async fn show(ctx: Context<'_>) -> Result<(), Error> {
+    let config = ctx.data().config();
+
+    let mut owners: Vec<String> = config
+        .owners
+        .iter()
+        .map(|owner| owner.mention().to_string())
+        .collect();
+    owners.sort();
+
+    let legacy_guild = match config.legacy_guild_id {
+        Some(guild_id) => guild_id.to_string(),
+        None => "none".to_string(),
+    };
+
+    ctx.send(|m| {
+        m.content(format!(
+            "**Prefix:** `{}`\n**Owners:** {}\n**Database:** `{}`\n**Legacy guild:** {}\n\
+             **Git branch:** `{}`\n**Pack repository:** `{}`\n**Disallowed packwiz subcommands:** {}",
+            config.prefix,
+            owners.join(", "),
+            config.db_path.display(),
+            legacy_guild,
+            config.git.branch,
+            config.packwiz.repo_path.display(),
+            config.packwiz.disallowed_subcommands.join(", ")
+        ))
+        .allowed_mentions(|a| a.empty_parse())
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Reloads the configuration file and environment.
+///
+/// If the new configuration is invalid, the current one is kept.
+#[poise::command(slash_command, prefix_command, check = "owner", ephemeral)]
+pub async fn reload(ctx: Context<'_>) -> Result<(), Error> {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            say!(ctx, "Kept the current configuration, {:#}", e);
+            return Ok(());
+        }
+    };
+
+    let restart_required = config.restart_required(&ctx.data().config());
+
+    *ctx.data().config.write().unwrap() = Arc::new(config);
+    info!("Configuration reloaded by {}", ctx.author().id);
+
+    match restart_required.is_empty() {
+        true => say!(ctx, "Configuration reloaded"),
+        false => say!(
+            ctx,
+            "Configuration reloaded, changes to {} take effect after a restart",
+            restart_required.join(", ")
+        ),
+    }
+
+    Ok(())
+}
+
+/// Changes the prefix of text commands in this server.
+///
+/// Without a prefix, the current one is shown. Mentioning the bot always works as a prefix.
+#[poise::command(slash_command, prefix_command, guild_only, check = "owner")]
+pub async fn prefix(
+    ctx: Context<'_>,
+    #[description = "New prefix, `default` to use the one from the config"] prefix: Option<String>,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let prefix = match prefix {
+        Some(prefix) => prefix,
+        None => {
+            say!(
+                ctx,
+                "The prefix of this server is `{}`",
+                ctx.data().prefix(ctx.guild_id())
+            );
+            return Ok(());
+        }
+    };
+
+    if prefix.is_empty()
+        || prefix.contains(char::is_whitespace)
+        || prefix.chars().count() > MAX_PREFIX_LENGTH
+    {
+        say!(
+            ctx,
+            "Prefixes must be 1 to {} characters long and can't contain spaces",
+            MAX_PREFIX_LENGTH
+        );
+        return Ok(());
+    }
+
+    let mut settings = store.settings()?;
+    settings.prefix = match prefix == "default" || prefix == ctx.data().config().prefix {
+        true => None,
+        false => Some(prefix),
+    };
+    store.set_settings(&settings)?;
+
+    say!(
+        ctx,
+        "The prefix of this server is now `{}`",
+        ctx.data().prefix(ctx.guild_id())
+    );
+
+    Ok(())
+}
@@ -1,34 +1,122 @@
-use crate::{say, Context};
+use crate::confirm::confirm;
+use crate::permissions::commands_manage;
+use crate::response::RichResponse;
+use crate::storage::{
+    Alias, Cooldown, CooldownScope, CustomCommand, FloodAction, FloodLimit, Mentions, Record,
+    ReplyMode, ResponseKind, Restriction, RestrictionMode, Revision, RevisionTarget, ScriptData,
+    Store,
+};
+use crate::utils::{alias_chain, aliases_of, guild_store, preview, resolve_alias, truncate};
+use crate::{say, script, slash, Context};
 use anyhow::Error;
+use chrono::{DateTime, Utc};
 use log::warn;
+use poise::serenity_prelude::{Attachment, AttachmentType};
+use poise::serenity_prelude::{GuildChannel, Mentionable, Role, UserId};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::str::from_utf8;
 
 /// Creates or updates a custom command
-#[poise::command(slash_command, prefix_command, owners_only)]
+///
+/// Use a name like `faq install` to create a named entry of the `faq` command.
+/// Scripted commands run their response as a Rhai script, embed commands take a JSON payload.
+/// Both may be wrapped in a code block.
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
 pub async fn setcommand(
     ctx: Context<'_>,
-    #[description = "Command name"] name: String,
-    #[description = "Response"] response: String,
+    #[description = "Command name, optionally followed by an entry name"]
+    #[autocomplete = "autocomplete_command"]
+    name: String,
+    #[description = "Response, supports placeholders like {user}, {args} or {random:a|b}"]
+    response: String,
+    #[description = "Description shown in the slash command picker"] description: Option<String>,
+    #[description = "Whether the response is text or a script, defaults to the current kind"]
+    kind: Option<ResponseKind>,
 ) -> Result<(), Error> {
-    let tree = &ctx.data().tree;
+    let store = guild_store(ctx)?;
 
-    if name.starts_with("--") {
-        say!(ctx, "Commands may not start with --.");
+    let words: Vec<&str> = name.split_whitespace().collect();
+
+    if words.is_empty() || words.len() > 2 {
+        say!(
+            ctx,
+            "Names must be a command, optionally followed by a single entry name"
+        );
         return Ok(());
     }
 
-    if ctx
-        .framework()
-        .options
-        .commands
-        .iter()
-        .any(|c| c.aliases.contains(&name.as_str()) || c.name == name)
-    {
+    if is_builtin(ctx, words[0]) || has_reserved_character(&name) {
         say!(ctx, "Invalid name");
         return Ok(());
     }
 
-    match tree.insert(name.to_ascii_lowercase(), response.as_str()) {
-        Ok(_) => say!(ctx, "Command inserted into database"),
+    let name = words.join(" ").to_ascii_lowercase();
+
+    // Aliases are resolved before commands, so a command with the same name would never run
+    let command_name = words[0].to_ascii_lowercase();
+    if store.contains::<Alias>(&command_name)? {
+        say!(
+            ctx,
+            "An alias named `{}` already exists, remove it with `rmalias` first",
+            command_name
+        );
+        return Ok(());
+    }
+
+    let author = ctx.author().id;
+
+    let previous = match store.get::<CustomCommand>(&name) {
+        Ok(previous) => previous,
+        Err(e) => {
+            warn!("Error getting command from database: {:?}", e);
+            say!(ctx, "Error getting command from database");
+            return Ok(());
+        }
+    };
+
+    let kind = kind
+        .or(previous.as_ref().map(|previous| previous.kind))
+        .unwrap_or_default();
+
+    let response = match kind {
+        ResponseKind::Text => response,
+        ResponseKind::Script | ResponseKind::Embed => strip_code_block(&response).to_string(),
+    };
+
+    if let Err(e) = check_response(kind, &response) {
+        say!(ctx, "{}", e);
+        return Ok(());
+    }
+
+    let mut command = updated_command(previous.clone(), response.clone(), author);
+    command.kind = kind;
+
+    if description.is_some() {
+        command.description = description;
+    }
+
+    match store.insert(&name, &command) {
+        Ok(_) => {
+            record_revision(
+                &store,
+                RevisionTarget::Command,
+                &name,
+                previous.as_ref().map(command_revision),
+                Some(response),
+                kind,
+                author,
+            )?;
+
+            match previous {
+                Some(_) => say!(ctx, "Command `{}` updated", name),
+                None => say!(ctx, "Command `{}` created", name),
+            }
+
+            sync_slash_commands(ctx, &store).await;
+        }
         Err(e) => {
             warn!("Error inserting command into database: {:?}", e);
             say!(ctx, "Error inserting command into database");
@@ -39,70 +127,1724 @@ pub async fn setcommand(
 }
 
 /// Creates or updates a custom alias
-#[poise::command(slash_command, prefix_command, owners_only)]
+///
+/// Aliases may point at commands, named entries like `faq install` or other aliases.
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
 pub async fn setalias(
     ctx: Context<'_>,
-    #[description = "Alias name"] name: String,
-    #[description = "Command name"] command: String,
+    #[description = "Alias name"]
+    #[autocomplete = "autocomplete_alias"]
+    name: String,
+    #[description = "Command or alias name"]
+    #[autocomplete = "autocomplete_target"]
+    #[rest]
+    command: String,
 ) -> Result<(), Error> {
-    let tree = &ctx.data().tree;
+    let store = guild_store(ctx)?;
 
-    if ctx
-        .framework()
-        .options
-        .commands
-        .iter()
-        .any(|c| c.aliases.contains(&name.as_str()) || c.name == name)
+    let name = name.trim().to_ascii_lowercase();
+    let command = normalize_name(&command);
+
+    // only the first word of a message is resolved as an alias
+    if name.is_empty()
+        || name.contains(char::is_whitespace)
+        || is_builtin(ctx, &name)
+        || has_reserved_character(&name)
     {
         say!(ctx, "Invalid name");
         return Ok(());
     }
 
-    if let Err(e) = tree.insert(
-        format!("alias-{}", name.to_ascii_lowercase()),
-        command.as_str(),
+    if is_command_name(&store, &name)? {
+        say!(ctx, "A command named `{}` already exists", name);
+        return Ok(());
+    }
+
+    let chain = match alias_chain(&store, &command) {
+        Ok(chain) => chain,
+        Err(e) => {
+            say!(ctx, "Can't point an alias at `{}`: {}", command, e);
+            return Ok(());
+        }
+    };
+
+    if chain.contains(&name) {
+        say!(
+            ctx,
+            "That would create an alias cycle: {} -> {}",
+            name,
+            chain.join(" -> ")
+        );
+        return Ok(());
+    }
+
+    if !store.contains::<CustomCommand>(chain.last().unwrap())? {
+        say!(ctx, "There is no command named `{}`", chain.last().unwrap());
+        return Ok(());
+    }
+
+    match store.insert(
+        &name,
+        &Alias {
+            command: command.clone(),
+        },
     ) {
-        warn!("Error inserting alias into database: {:?}", e);
-        say!(ctx, "Error inserting alias into database");
-    } else {
-        say!(ctx, "Alias inserted into database");
+        Ok(previous) => {
+            record_revision(
+                &store,
+                RevisionTarget::Alias,
+                &name,
+                previous.as_ref().map(alias_revision),
+                Some(command),
+                ResponseKind::Text,
+                ctx.author().id,
+            )?;
+
+            say!(ctx, "Alias inserted into database");
+        }
+        Err(e) => {
+            warn!("Error inserting alias into database: {:?}", e);
+            say!(ctx, "Error inserting alias into database");
+        }
     }
 
     Ok(())
 }
 
 /// Removes a custom command
-#[poise::command(slash_command, prefix_command, owners_only)]
+///
+/// Aliases pointing at the command are kept unless `cascade` is set.
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
 pub async fn rmcommand(
     ctx: Context<'_>,
-    #[description = "Command name"] name: String,
+    #[description = "Command name"]
+    #[autocomplete = "autocomplete_command"]
+    name: String,
+    #[description = "Also remove aliases pointing at the command"] cascade: Option<bool>,
 ) -> Result<(), Error> {
-    let tree = &ctx.data().tree;
+    let store = guild_store(ctx)?;
+
+    let name = normalize_name(&name);
+
+    if !store.contains::<CustomCommand>(&name)? {
+        say!(ctx, "There is no command named `{}`", name);
+        return Ok(());
+    }
+
+    let aliases = aliases_of(&store, &name)?;
+    let cascade = cascade.unwrap_or(false);
+
+    let mut prompt = format!("Remove the command `{}`?", name);
+
+    if !aliases.is_empty() {
+        prompt.push_str(&format!(
+            "\n{} {}",
+            match cascade {
+                true => "These aliases are removed too:",
+                false => "These aliases will point at nothing:",
+            },
+            aliases.join(", ")
+        ));
+    }
+
+    if !confirm(ctx, &prompt).await? {
+        return Ok(());
+    }
+
+    match store.remove::<CustomCommand>(&name) {
+        Ok(Some(previous)) => {
+            record_revision(
+                &store,
+                RevisionTarget::Command,
+                &name,
+                Some(command_revision(&previous)),
+                None,
+                previous.kind,
+                ctx.author().id,
+            )?;
+        }
+        Ok(None) => {
+            say!(ctx, "There is no command named `{}`", name);
+            return Ok(());
+        }
+        Err(e) => {
+            warn!("Error removing command from database: {:?}", e);
+            say!(ctx, "Error removing command from database");
+            return Ok(());
+        }
+    }
+
+    store.remove::<ScriptData>(&name)?;
+
+    say!(ctx, "Command removed from database");
+    sync_slash_commands(ctx, &store).await;
+
+    if aliases.is_empty() {
+        return Ok(());
+    }
+
+    if cascade {
+        for alias in &aliases {
+            if let Some(previous) = store.remove::<Alias>(alias)? {
+                record_revision(
+                    &store,
+                    RevisionTarget::Alias,
+                    alias,
+                    Some(alias_revision(&previous)),
+                    None,
+                    ResponseKind::Text,
+                    ctx.author().id,
+                )?;
+            }
+        }
 
-    if let Err(e) = tree.remove(name.to_ascii_lowercase()) {
-        warn!("Error removing command from database: {:?}", e);
-        say!(ctx, "Error removing command from database");
+        say!(ctx, "Also removed aliases: {}", aliases.join(", "));
     } else {
-        say!(ctx, "Command removed from database");
+        say!(
+            ctx,
+            "These aliases now point at nothing: {}\nRemove them with `rmalias` or rerun with cascade.",
+            aliases.join(", ")
+        );
     }
 
     Ok(())
 }
 
 /// Removes a custom alias
-#[poise::command(slash_command, prefix_command, owners_only)]
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
 pub async fn rmalias(
     ctx: Context<'_>,
-    #[description = "Alias name"] name: String,
+    #[description = "Alias name"]
+    #[autocomplete = "autocomplete_alias"]
+    name: String,
 ) -> Result<(), Error> {
-    let tree = &ctx.data().tree;
+    let store = guild_store(ctx)?;
 
-    if let Err(e) = tree.remove(format!("alias-{}", name.to_ascii_lowercase())) {
-        warn!("Error removing alias from database: {:?}", e);
-        say!(ctx, "Error removing alias from database");
-    } else {
-        say!(ctx, "Alias removed from database");
+    let name = name.to_ascii_lowercase();
+
+    let alias = match store.get::<Alias>(&name)? {
+        Some(alias) => alias,
+        None => {
+            say!(ctx, "There is no alias named `{}`", name);
+            return Ok(());
+        }
+    };
+
+    let prompt = format!(
+        "Remove the alias `{}` pointing at `{}`?",
+        name, alias.command
+    );
+
+    if !confirm(ctx, &prompt).await? {
+        return Ok(());
+    }
+
+    match store.remove::<Alias>(&name) {
+        Ok(Some(previous)) => {
+            record_revision(
+                &store,
+                RevisionTarget::Alias,
+                &name,
+                Some(alias_revision(&previous)),
+                None,
+                ResponseKind::Text,
+                ctx.author().id,
+            )?;
+
+            say!(ctx, "Alias removed from database");
+        }
+        Ok(None) => say!(ctx, "There is no alias named `{}`", name),
+        Err(e) => {
+            warn!("Error removing alias from database: {:?}", e);
+            say!(ctx, "Error removing alias from database");
+        }
+    }
+
+    Ok(())
+}
+
+/// Inspect custom aliases
+#[poise::command(slash_command, prefix_command, guild_only, subcommands("alias_list"))]
+pub async fn alias(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Lists all aliases pointing at a command
+#[poise::command(slash_command, prefix_command, guild_only, rename = "list")]
+pub async fn alias_list(
+    ctx: Context<'_>,
+    #[description = "Command name"]
+    #[autocomplete = "autocomplete_target"]
+    #[rest]
+    command: String,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let command = resolve_alias(&store, &normalize_name(&command))?;
+
+    let mut lines = Vec::new();
+
+    for alias in aliases_of(&store, &command)? {
+        lines.push(format!("`{}`", alias_chain(&store, &alias)?.join("` -> `")));
+    }
+
+    match lines.is_empty() {
+        true => say!(ctx, "No aliases point at `{}`", command),
+        false => say!(ctx, "Aliases of `{}`:\n{}", command, lines.join("\n")),
+    }
+
+    Ok(())
+}
+
+pub const ENTRIES_PER_PAGE: usize = 10;
+pub const PREVIEW_LENGTH: usize = 80;
+/// Discord shows at most this many autocomplete suggestions
+const MAX_SUGGESTIONS: usize = 25;
+const DIFF_LENGTH: usize = 1800;
+/// Longest import summary sent, leaving room for the rest of the message
+const MAX_SUMMARY_LENGTH: usize = 1900;
+
+/// Numbered revisions of a command or alias
+type History = (RevisionTarget, Vec<(u32, Revision)>);
+
+/// Browse the custom commands of this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands(
+        "list",
+        "search",
+        "info",
+        "history",
+        "diff",
+        "rollback",
+        "export",
+        "import",
+        "slash",
+        "cooldown",
+        "flood",
+        "restrict",
+        "suggestions",
+        "mode",
+        "mentions"
+    )
+)]
+pub async fn commands(ctx: Context<'_>) -> Result<(), Error> {
+    overview(ctx, None).await
+}
+
+/// Lists all custom commands and their aliases
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    overview(ctx, None).await
+}
+
+/// Searches custom commands by name, alias or response
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "Text to search for"]
+    #[rest]
+    query: String,
+) -> Result<(), Error> {
+    overview(ctx, Some(query.to_lowercase())).await
+}
+
+/// Shows details and usage statistics of a custom command
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn info(
+    ctx: Context<'_>,
+    #[description = "Command name"]
+    #[autocomplete = "autocomplete_target"]
+    #[rest]
+    name: String,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let name = normalize_name(&name);
+    let name = resolve_alias(&store, &name)?;
+
+    let command = match store.get::<CustomCommand>(&name)? {
+        Some(command) => command,
+        None => {
+            say!(ctx, "Command not found");
+            return Ok(());
+        }
+    };
+
+    let aliases = aliases_of(&store, &name)?;
+
+    let user = |id: Option<UserId>| id.map_or("unknown".to_string(), |id| id.mention().to_string());
+    let time = |time: Option<DateTime<Utc>>| {
+        time.map_or("unknown".to_string(), |time| {
+            format!("<t:{}:R>", time.timestamp())
+        })
+    };
+
+    ctx.send(|m| {
+        m.allowed_mentions(|a| a.empty_parse()).embed(|e| {
+            e.title(format!("{}{}", ctx.data().prefix(ctx.guild_id()), name))
+                .description(preview(&command.response, PREVIEW_LENGTH))
+                .field("Created by", user(command.author), true)
+                .field("Created", time(command.created_at), true)
+                .field("\u{200b}", "\u{200b}", true)
+                .field("Last changed by", user(command.updated_by), true)
+                .field("Last changed", time(command.updated_at), true)
+                .field("\u{200b}", "\u{200b}", true)
+                .field("Uses", command.uses, true)
+                .field("Last used", time(command.last_used_at), true)
+                .field(
+                    "Cooldown",
+                    match &command.cooldown {
+                        Some(cooldown) => {
+                            format!("{}s per {}", cooldown.seconds, cooldown.scope.name())
+                        }
+                        None => "none".to_string(),
+                    },
+                    true,
+                )
+                .field("Reply mode", command.reply_mode.name(), true)
+                .field("Mentions", describe_mentions(&command.mentions), true)
+                .field("Roles", describe_restriction(&command.roles), true)
+                .field("Channels", describe_restriction(&command.channels), true)
+                .field(
+                    "Aliases",
+                    match aliases.is_empty() {
+                        true => "none".to_string(),
+                        false => aliases.join(", "),
+                    },
+                    true,
+                )
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Shows the edit history of a custom command or alias
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn history(
+    ctx: Context<'_>,
+    #[description = "Command or alias name"]
+    #[autocomplete = "autocomplete_target"]
+    #[rest]
+    name: String,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let name = normalize_name(&name);
+
+    let (target, revisions) = match find_revisions(&store, &name)? {
+        Some(history) => history,
+        None => {
+            say!(ctx, "No history found for `{}`", name);
+            return Ok(());
+        }
+    };
+
+    let entries: Vec<String> = revisions
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(index, (number, revision))| {
+            let author = revision
+                .author
+                .map_or("unknown".to_string(), |id| id.mention().to_string());
+            let time = revision
+                .timestamp
+                .map_or("unknown time".to_string(), |time| {
+                    format!("<t:{}:R>", time.timestamp())
+                });
+            let content = match (&revision.content, target) {
+                (Some(response), RevisionTarget::Command) => preview(response, PREVIEW_LENGTH),
+                (Some(command), RevisionTarget::Alias) => format!("alias of `{}`", command),
+                (None, _) => "*removed*".to_string(),
+            };
+            let kind = match kind_change(&revisions, index) {
+                Some((_, kind)) => format!(", kind changed to {}", kind.name()),
+                None => String::new(),
+            };
+
+            format!(
+                "**#{}** by {}, {}{}\n> {}",
+                number, author, time, kind, content
+            )
+        })
+        .collect();
+
+    let pages: Vec<String> = entries
+        .chunks(ENTRIES_PER_PAGE)
+        .map(|page| page.join("\n\n"))
+        .collect();
+    let pages: Vec<&str> = pages.iter().map(String::as_str).collect();
+
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
+
+/// Shows the changes made by a revision of a custom command or alias
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn diff(
+    ctx: Context<'_>,
+    #[description = "Command or alias name"]
+    #[autocomplete = "autocomplete_target"]
+    name: String,
+    #[description = "Revision number, see history"] revision: u32,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let name = normalize_name(&name);
+
+    let revisions = match find_revisions(&store, &name)? {
+        Some((_, revisions)) => revisions,
+        None => {
+            say!(ctx, "No history found for `{}`", name);
+            return Ok(());
+        }
+    };
+
+    let index = match revisions.iter().position(|(number, _)| *number == revision) {
+        Some(index) => index,
+        None => {
+            say!(ctx, "Revision #{} of `{}` doesn't exist", revision, name);
+            return Ok(());
+        }
+    };
+
+    let before = match index {
+        0 => "",
+        _ => revisions[index - 1]
+            .1
+            .content
+            .as_deref()
+            .unwrap_or_default(),
+    };
+    let after = revisions[index].1.content.as_deref().unwrap_or_default();
+
+    let mut output = String::new();
+
+    for line in diff::lines(before, after) {
+        let line = match line {
+            diff::Result::Left(line) => format!("- {}\n", line),
+            diff::Result::Right(line) => format!("+ {}\n", line),
+            diff::Result::Both(line, _) => format!("  {}\n", line),
+        };
+
+        output.push_str(&line.replace("```", "`\u{200b}``"));
+    }
+
+    if output.chars().count() > DIFF_LENGTH {
+        output = output.chars().take(DIFF_LENGTH).collect();
+        output.push_str("\n...");
+    }
+
+    let kind = match kind_change(&revisions, index) {
+        Some((before, after)) => {
+            format!("\nKind changed from {} to {}", before.name(), after.name())
+        }
+        None => String::new(),
+    };
+
+    say!(
+        ctx,
+        "Changes made by revision #{}:{}\n```diff\n{}```",
+        revision,
+        kind,
+        output
+    );
+
+    Ok(())
+}
+
+/// Restores a custom command or alias to an earlier revision
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn rollback(
+    ctx: Context<'_>,
+    #[description = "Command or alias name"]
+    #[autocomplete = "autocomplete_target"]
+    name: String,
+    #[description = "Revision number, see history"] revision: u32,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+    let author = ctx.author().id;
+
+    let name = normalize_name(&name);
+
+    let (target, revisions) = match find_revisions(&store, &name)? {
+        Some(history) => history,
+        None => {
+            say!(ctx, "No history found for `{}`", name);
+            return Ok(());
+        }
+    };
+
+    let (content, kind) = match revisions
+        .into_iter()
+        .find(|(number, _)| *number == revision)
+    {
+        Some((_, revision)) => (revision.content, revision.kind),
+        None => {
+            say!(ctx, "Revision #{} of `{}` doesn't exist", revision, name);
+            return Ok(());
+        }
+    };
+
+    let previous = match (target, content.clone()) {
+        (RevisionTarget::Command, Some(response)) => {
+            let previous = store.get::<CustomCommand>(&name)?;
+            let command = CustomCommand {
+                kind,
+                ..updated_command(previous.clone(), response, author)
+            };
+            store.insert(&name, &command)?;
+            previous.as_ref().map(command_revision)
+        }
+        (RevisionTarget::Command, None) => store
+            .remove::<CustomCommand>(&name)?
+            .as_ref()
+            .map(command_revision),
+        (RevisionTarget::Alias, Some(command)) => store
+            .insert(&name, &Alias { command })?
+            .as_ref()
+            .map(alias_revision),
+        (RevisionTarget::Alias, None) => store.remove::<Alias>(&name)?.as_ref().map(alias_revision),
+    };
+
+    record_revision(&store, target, &name, previous, content, kind, author)?;
+    say!(ctx, "Rolled `{}` back to revision #{}", name, revision);
+
+    if target == RevisionTarget::Command {
+        sync_slash_commands(ctx, &store).await;
+    }
+
+    Ok(())
+}
+
+/// Uploads all custom commands and aliases as a file
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn export(
+    ctx: Context<'_>,
+    #[description = "File format, defaults to JSON"] format: Option<ExportFormat>,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let mut export = Export::default();
+
+    for entry in store.scan::<CustomCommand>("") {
+        let (name, command) = entry?;
+        export.commands.insert(name, command);
+    }
+
+    for entry in store.scan::<Alias>("") {
+        let (name, alias) = entry?;
+        export.aliases.insert(name, alias.command);
     }
 
+    let (data, extension) = match format.unwrap_or(ExportFormat::Json) {
+        ExportFormat::Json => (serde_json::to_vec_pretty(&export)?, "json"),
+        ExportFormat::Toml => (toml::to_string_pretty(&export)?.into_bytes(), "toml"),
+    };
+
+    ctx.send(|m| {
+        m.content(format!(
+            "Exported {} commands and {} aliases",
+            export.commands.len(),
+            export.aliases.len()
+        ))
+        .attachment(AttachmentType::Bytes {
+            data: Cow::Owned(data),
+            filename: format!("commands.{}", extension),
+        })
+    })
+    .await?;
+
     Ok(())
 }
+
+/// Imports custom commands and aliases from an exported file
+///
+/// In merge mode entries missing from the file are kept, in replace mode they are removed.
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn import(
+    ctx: Context<'_>,
+    #[description = "JSON or TOML file created by export"] file: Attachment,
+    #[description = "Whether to keep entries missing from the file, defaults to merge"]
+    mode: Option<ImportMode>,
+    #[description = "Only show what would change"] dry_run: Option<bool>,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+    let author = ctx.author().id;
+
+    let data = file.download().await?;
+
+    let parsed: Result<Export, Error> = match file.filename.ends_with(".toml") {
+        true => from_utf8(&data)
+            .map_err(Error::from)
+            .and_then(|data| Ok(toml::from_str(data)?)),
+        false => serde_json::from_slice(&data).map_err(Error::from),
+    };
+
+    let mut export = match parsed {
+        Ok(export) => export,
+        Err(e) => {
+            say!(ctx, "Couldn't read `{}`: {}", file.filename, e);
+            return Ok(());
+        }
+    };
+
+    export.commands = export
+        .commands
+        .into_iter()
+        .map(|(name, command)| (normalize_name(&name), command))
+        .collect();
+    export.aliases = export
+        .aliases
+        .into_iter()
+        .map(|(name, command)| (normalize_name(&name), normalize_name(&command)))
+        .collect();
+
+    let replace = matches!(mode, Some(ImportMode::Replace));
+
+    let mut skipped = Vec::new();
+
+    for name in export.commands.keys() {
+        // aliases are resolved first, so commands named like one would never run
+        let shadowed = match name.split_whitespace().next() {
+            Some(command) => {
+                export.aliases.contains_key(command)
+                    || (!replace && store.contains::<Alias>(command)?)
+            }
+            None => false,
+        };
+
+        if !is_valid_command_name(ctx, name) || shadowed {
+            skipped.push(name.clone());
+        }
+    }
+
+    let mut invalid = Vec::new();
+
+    for (name, command) in &export.commands {
+        if let Err(e) = check_response(command.kind, &command.response) {
+            invalid.push(format!("`{}`: {}", name, e));
+            skipped.push(name.clone());
+        }
+
+        if command
+            .cooldown
+            .as_ref()
+            .is_some_and(|cooldown| cooldown.seconds > Cooldown::MAX_SECONDS)
+        {
+            invalid.push(format!("`{}`: cooldown longer than a week", name));
+            skipped.push(name.clone());
+        }
+    }
+
+    for name in export.aliases.keys() {
+        // aliases would shadow commands named like them, or named entries of those
+        let shadowing = export
+            .commands
+            .keys()
+            .any(|command| command.split_whitespace().next() == Some(name.as_str()))
+            || (!replace && is_command_name(&store, name)?);
+
+        if name.contains(' ') || !is_valid_command_name(ctx, name) || shadowing {
+            skipped.push(name.clone());
+        }
+    }
+
+    skipped.sort();
+    skipped.dedup();
+
+    export.commands.retain(|name, _| !skipped.contains(name));
+    export.aliases.retain(|name, _| !skipped.contains(name));
+
+    let mut commands = ImportSummary::default();
+
+    for (name, command) in &export.commands {
+        match store.get::<CustomCommand>(name)? {
+            None => commands.added.push(name.clone()),
+            Some(existing) if !existing.same_settings(command) => {
+                commands.changed.push(name.clone())
+            }
+            Some(_) => commands.unchanged += 1,
+        }
+    }
+
+    let mut aliases = ImportSummary::default();
+
+    for (name, command) in &export.aliases {
+        match store.get::<Alias>(name)? {
+            None => aliases.added.push(name.clone()),
+            Some(existing) if existing.command != *command => aliases.changed.push(name.clone()),
+            Some(_) => aliases.unchanged += 1,
+        }
+    }
+
+    if replace {
+        for entry in store.scan::<CustomCommand>("") {
+            let (name, _) = entry?;
+
+            if !export.commands.contains_key(&name) {
+                commands.removed.push(name);
+            }
+        }
+
+        for entry in store.scan::<Alias>("") {
+            let (name, _) = entry?;
+
+            if !export.aliases.contains_key(&name) {
+                aliases.removed.push(name);
+            }
+        }
+    }
+
+    let mut summary = format!("**Commands:** {}\n**Aliases:** {}", commands, aliases);
+
+    if !skipped.is_empty() {
+        summary.push_str(&format!(
+            "\nSkipped invalid names, names of built-in commands, names shadowed by aliases \
+             or shadowing commands, and invalid responses: {}",
+            skipped.join(", ")
+        ));
+    }
+
+    for problem in &invalid {
+        summary.push_str(&format!("\n- {}", problem));
+    }
+
+    if dry_run.unwrap_or(false) {
+        say!(
+            ctx,
+            "Dry run, nothing was changed.\n{}",
+            truncate(&summary, MAX_SUMMARY_LENGTH)
+        );
+        return Ok(());
+    }
+
+    let prompt = format!("Import `{}`?\n{}", file.filename, summary);
+
+    if !confirm(ctx, &prompt).await? {
+        return Ok(());
+    }
+
+    for name in commands.added.iter().chain(&commands.changed) {
+        let command = export.commands[name].clone();
+        let previous = store.get::<CustomCommand>(name)?;
+
+        // new commands keep the metadata from the file, changed ones keep their local metadata
+        let command = match previous.clone() {
+            Some(previous) => CustomCommand {
+                updated_by: Some(author),
+                updated_at: Some(Utc::now()),
+                ..previous.with_settings(command)
+            },
+            None => command,
+        };
+
+        store.insert(name, &command)?;
+
+        record_revision(
+            &store,
+            RevisionTarget::Command,
+            name,
+            previous.as_ref().map(command_revision),
+            Some(command.response),
+            command.kind,
+            author,
+        )?;
+    }
+
+    for name in aliases.added.iter().chain(&aliases.changed) {
+        let command = export.aliases[name].clone();
+        let previous = store.insert(
+            name,
+            &Alias {
+                command: command.clone(),
+            },
+        )?;
+
+        record_revision(
+            &store,
+            RevisionTarget::Alias,
+            name,
+            previous.as_ref().map(alias_revision),
+            Some(command),
+            ResponseKind::Text,
+            author,
+        )?;
+    }
+
+    for name in &commands.removed {
+        if let Some(previous) = store.remove::<CustomCommand>(name)? {
+            let kind = previous.kind;
+            let previous = command_revision(&previous);
+            record_revision(
+                &store,
+                RevisionTarget::Command,
+                name,
+                Some(previous),
+                None,
+                kind,
+                author,
+            )?;
+        }
+    }
+
+    for name in &aliases.removed {
+        if let Some(previous) = store.remove::<Alias>(name)? {
+            let previous = alias_revision(&previous);
+            record_revision(
+                &store,
+                RevisionTarget::Alias,
+                name,
+                Some(previous),
+                None,
+                ResponseKind::Text,
+                author,
+            )?;
+        }
+    }
+
+    let mut broken = Vec::new();
+
+    for entry in store.scan::<Alias>("") {
+        let (name, _) = entry?;
+
+        match resolve_alias(&store, &name) {
+            Ok(command) if store.contains::<CustomCommand>(&command)? => (),
+            _ => broken.push(name),
+        }
+    }
+
+    if !broken.is_empty() {
+        summary.push_str(&format!(
+            "\nThese aliases don't point at a command: {}",
+            broken.join(", ")
+        ));
+    }
+
+    say!(
+        ctx,
+        "Import finished.\n{}",
+        truncate(&summary, MAX_SUMMARY_LENGTH)
+    );
+    sync_slash_commands(ctx, &store).await;
+
+    Ok(())
+}
+
+/// Registers custom commands as slash commands of this server
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn slash(
+    ctx: Context<'_>,
+    #[description = "Whether custom commands should show up in the slash command picker"]
+    enabled: bool,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let mut settings = store.settings()?;
+    settings.slash_commands = enabled;
+    store.set_settings(&settings)?;
+
+    let guild_id = ctx.guild_id().unwrap();
+
+    match slash::sync(
+        ctx.http(),
+        &ctx.framework().options.commands,
+        guild_id,
+        &store,
+    )
+    .await
+    {
+        Ok(count) if enabled => say!(ctx, "Registered {} custom slash commands", count),
+        Ok(_) => say!(ctx, "Removed custom slash commands"),
+        Err(e) => {
+            warn!("Error syncing slash commands: {:?}", e);
+            say!(ctx, "Error syncing slash commands: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Suggests similar commands when an unknown command is used
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn suggestions(
+    ctx: Context<'_>,
+    #[description = "Whether to reply with \"did you mean\" suggestions"] enabled: bool,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let mut settings = store.settings()?;
+    settings.disable_suggestions = !enabled;
+    store.set_settings(&settings)?;
+
+    match enabled {
+        true => say!(ctx, "Enabled suggestions for unknown commands"),
+        false => say!(ctx, "Disabled suggestions for unknown commands"),
+    }
+
+    Ok(())
+}
+
+/// Sets how long a custom command can't be used again after it was used
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn cooldown(
+    ctx: Context<'_>,
+    #[description = "Command name"]
+    #[autocomplete = "autocomplete_command"]
+    name: String,
+    #[description = "Cooldown in seconds, 0 to remove it"] seconds: u64,
+    #[description = "Who the cooldown applies to, defaults to each user"] scope: Option<
+        CooldownScope,
+    >,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let name = normalize_name(&name);
+    let name = resolve_alias(&store, &name)?;
+
+    if seconds > Cooldown::MAX_SECONDS {
+        say!(
+            ctx,
+            "Cooldowns can be at most {} seconds, a week",
+            Cooldown::MAX_SECONDS
+        );
+        return Ok(());
+    }
+
+    let cooldown = match seconds {
+        0 => None,
+        seconds => Some(Cooldown {
+            seconds,
+            scope: scope.unwrap_or(CooldownScope::User),
+        }),
+    };
+
+    let updated = store.update::<CustomCommand>(&name, |command| {
+        command.cooldown = cooldown.clone();
+    })?;
+
+    match (updated, cooldown) {
+        (None, _) => say!(ctx, "There is no command named `{}`", name),
+        (Some(_), None) => say!(ctx, "Removed the cooldown of `{}`", name),
+        (Some(_), Some(cooldown)) => say!(
+            ctx,
+            "`{}` can be used once every {} seconds per {}",
+            name,
+            cooldown.seconds,
+            cooldown.scope.name()
+        ),
+    }
+
+    Ok(())
+}
+
+/// Restricts which roles may use a custom command and in which channels.
+///
+/// Without a role or channel, the current restrictions are shown, or all of them are cleared.
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn restrict(
+    ctx: Context<'_>,
+    #[description = "Command name"]
+    #[autocomplete = "autocomplete_command"]
+    name: String,
+    #[description = "Add to the allowlist or denylist, or remove from it"] action: RestrictAction,
+    #[description = "Role to add or remove"] role: Option<Role>,
+    #[description = "Channel to add or remove"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let name = normalize_name(&name);
+    let name = resolve_alias(&store, &name)?;
+
+    let showing = role.is_none() && channel.is_none() && action != RestrictAction::Clear;
+    let mut conflict = None;
+
+    let updated = store.update::<CustomCommand>(&name, |command| {
+        if showing {
+            return;
+        }
+
+        if role.is_none() && channel.is_none() {
+            command.roles = None;
+            command.channels = None;
+            return;
+        }
+
+        // change copies, so nothing is applied if either restriction conflicts
+        let mut roles = command.roles.clone();
+        let mut channels = command.channels.clone();
+
+        if let Some(role) = &role {
+            if let Err(mode) = restrict_id(&mut roles, action, role.id) {
+                conflict = Some(("Roles", mode));
+            }
+        }
+
+        if let Some(channel) = &channel {
+            if let Err(mode) = restrict_id(&mut channels, action, channel.id) {
+                conflict = Some(("Channels", mode));
+            }
+        }
+
+        if conflict.is_none() {
+            command.roles = roles;
+            command.channels = channels;
+        }
+    })?;
+
+    let command = match updated {
+        Some(command) => command,
+        None => {
+            say!(ctx, "There is no command named `{}`", name);
+            return Ok(());
+        }
+    };
+
+    let status = match conflict {
+        Some((kind, mode)) => format!(
+            "{} of `{}` are already restricted by a {} list, remove or clear them first",
+            kind,
+            name,
+            mode.name()
+        ),
+        None if showing => format!("Restrictions of `{}`", name),
+        None => format!("Updated the restrictions of `{}`", name),
+    };
+
+    ctx.send(|m| {
+        m.allowed_mentions(|a| a.empty_parse()).content(format!(
+            "{}\nRoles: {}\nChannels: {}",
+            status,
+            describe_restriction(&command.roles),
+            describe_restriction(&command.channels)
+        ))
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Sets where the response of a custom command is sent
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn mode(
+    ctx: Context<'_>,
+    #[description = "Command name"]
+    #[autocomplete = "autocomplete_command"]
+    name: String,
+    #[description = "Send in the channel, reply, DM the invoker or post in a thread"]
+    mode: ReplyMode,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let name = normalize_name(&name);
+    let name = resolve_alias(&store, &name)?;
+
+    let updated = store.update::<CustomCommand>(&name, |command| command.reply_mode = mode)?;
+
+    match updated {
+        Some(_) => say!(ctx, "`{}` now responds with mode {}", name, mode.name()),
+        None => say!(ctx, "There is no command named `{}`", name),
+    }
+
+    Ok(())
+}
+
+/// Allows the response of a custom command to ping users, roles or everyone.
+///
+/// Responses don't ping anyone unless allowed here. Without options, the current settings are shown.
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn mentions(
+    ctx: Context<'_>,
+    #[description = "Command name"]
+    #[autocomplete = "autocomplete_command"]
+    name: String,
+    #[description = "Allow pinging users, including the invoker when replying"] users: Option<bool>,
+    #[description = "Allow pinging roles"] roles: Option<bool>,
+    #[description = "Allow pinging @everyone and @here"] everyone: Option<bool>,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let name = normalize_name(&name);
+    let name = resolve_alias(&store, &name)?;
+
+    let updated = store.update::<CustomCommand>(&name, |command| {
+        let mentions = &mut command.mentions;
+
+        mentions.users = users.unwrap_or(mentions.users);
+        mentions.roles = roles.unwrap_or(mentions.roles);
+        mentions.everyone = everyone.unwrap_or(mentions.everyone);
+    })?;
+
+    match updated {
+        Some(command) => say!(
+            ctx,
+            "`{}` may ping: {}",
+            name,
+            describe_mentions(&command.mentions)
+        ),
+        None => say!(ctx, "There is no command named `{}`", name),
+    }
+
+    Ok(())
+}
+
+/// Limits how many custom commands a single user may use in a time frame
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn flood(
+    ctx: Context<'_>,
+    #[description = "Number of commands allowed, 0 to disable the limit"] messages: u32,
+    #[description = "Time frame in seconds"] seconds: u64,
+    #[description = "What to do when a user exceeds the limit, defaults to ignoring them"]
+    action: Option<FloodAction>,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let mut settings = store.settings()?;
+
+    settings.flood_limit = match (messages, seconds) {
+        (0, _) | (_, 0) => None,
+        (messages, seconds) => Some(FloodLimit {
+            messages,
+            seconds,
+            action: action.unwrap_or(FloodAction::Ignore),
+        }),
+    };
+
+    store.set_settings(&settings)?;
+
+    match settings.flood_limit {
+        Some(limit) => say!(
+            ctx,
+            "Users may use {} custom commands every {} seconds",
+            limit.messages,
+            limit.seconds
+        ),
+        None => say!(ctx, "Disabled the flood limit"),
+    }
+
+    Ok(())
+}
+
+/// Update the slash commands of the guild after custom commands changed, if enabled
+async fn sync_slash_commands(ctx: Context<'_>, store: &Store) {
+    match store.settings() {
+        Ok(settings) if settings.slash_commands => (),
+        _ => return,
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+
+    if let Err(e) = slash::sync(
+        ctx.http(),
+        &ctx.framework().options.commands,
+        guild_id,
+        store,
+    )
+    .await
+    {
+        warn!("Error syncing slash commands: {:?}", e);
+        say!(ctx, "Error syncing slash commands: {}", e);
+    }
+}
+
+/// Change to the role or channel restrictions of a command
+#[derive(poise::ChoiceParameter, Clone, Copy, PartialEq, Eq)]
+pub enum RestrictAction {
+    #[name = "allow"]
+    Allow,
+    #[name = "deny"]
+    Deny,
+    #[name = "remove"]
+    Remove,
+    #[name = "clear"]
+    Clear,
+}
+
+/// Add an id to a restriction or remove it.
+/// Fails with the current mode if the id would be added to a list of the other mode.
+pub fn restrict_id<T: PartialEq>(
+    restriction: &mut Option<Restriction<T>>,
+    action: RestrictAction,
+    id: T,
+) -> Result<(), RestrictionMode> {
+    let mode = match action {
+        RestrictAction::Allow => RestrictionMode::Allow,
+        RestrictAction::Deny => RestrictionMode::Deny,
+        RestrictAction::Remove => {
+            if let Some(current) = restriction {
+                current.ids.retain(|current| *current != id);
+
+                if current.ids.is_empty() {
+                    *restriction = None;
+                }
+            }
+
+            return Ok(());
+        }
+        RestrictAction::Clear => {
+            *restriction = None;
+            return Ok(());
+        }
+    };
+
+    match restriction {
+        Some(current) if current.mode != mode => return Err(current.mode),
+        Some(current) if !current.ids.contains(&id) => current.ids.push(id),
+        Some(_) => (),
+        None => {
+            *restriction = Some(Restriction {
+                mode,
+                ids: vec![id],
+            })
+        }
+    }
+
+    Ok(())
+}
+
+/// Describe a role or channel restriction, mentioning everything it lists
+pub fn describe_restriction<T: Mentionable>(restriction: &Option<Restriction<T>>) -> String {
+    match restriction {
+        Some(restriction) => format!(
+            "{} {}",
+            restriction.mode.name(),
+            restriction
+                .ids
+                .iter()
+                .map(|id| id.mention().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        None => "none".to_string(),
+    }
+}
+
+/// List the kinds of mentions a command may ping
+fn describe_mentions(mentions: &Mentions) -> String {
+    let allowed: Vec<&str> = [
+        (mentions.users, "users"),
+        (mentions.roles, "roles"),
+        (mentions.everyone, "everyone"),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, kind)| enabled.then_some(kind))
+    .collect();
+
+    match allowed.is_empty() {
+        true => "nothing".to_string(),
+        false => allowed.join(", "),
+    }
+}
+
+/// File format for exported commands
+#[derive(poise::ChoiceParameter, Clone, Copy)]
+pub enum ExportFormat {
+    #[name = "JSON"]
+    Json,
+    #[name = "TOML"]
+    Toml,
+}
+
+/// How to handle existing entries that are missing from an imported file
+#[derive(poise::ChoiceParameter, Clone, Copy)]
+pub enum ImportMode {
+    #[name = "merge"]
+    Merge,
+    #[name = "replace"]
+    Replace,
+}
+
+/// Custom commands and aliases of a guild as written by `/commands export`
+#[derive(Serialize, Deserialize, Default)]
+struct Export {
+    #[serde(default)]
+    commands: BTreeMap<String, CustomCommand>,
+    /// Alias names mapped to the command they point at
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+}
+
+/// Changes an import makes to either commands or aliases
+#[derive(Default)]
+struct ImportSummary {
+    added: Vec<String>,
+    changed: Vec<String>,
+    removed: Vec<String>,
+    unchanged: usize,
+}
+
+impl Display for ImportSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} changed, {} removed, {} unchanged",
+            self.added.len(),
+            self.changed.len(),
+            self.removed.len(),
+            self.unchanged
+        )?;
+
+        for (label, names) in [
+            ("Added", &self.added),
+            ("Changed", &self.changed),
+            ("Removed", &self.removed),
+        ] {
+            if !names.is_empty() {
+                write!(f, "\n- {}: {}", label, names.join(", "))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Suggest names of existing custom commands
+async fn autocomplete_command(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    complete_names::<CustomCommand>(ctx, partial)
+}
+
+/// Suggest names of existing aliases
+async fn autocomplete_alias(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    complete_names::<Alias>(ctx, partial)
+}
+
+/// Suggest names of existing custom commands and aliases
+async fn autocomplete_target(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let mut names = complete_names::<CustomCommand>(ctx, partial);
+    names.extend(complete_names::<Alias>(ctx, partial));
+    names.sort();
+    names.truncate(MAX_SUGGESTIONS);
+
+    names
+}
+
+/// Get the names of records starting with what was typed so far
+pub fn complete_names<R: Record>(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let store = match guild_store(ctx) {
+        Ok(store) => store,
+        Err(_) => return Vec::new(),
+    };
+
+    store
+        .scan::<R>(&partial.trim_start().to_ascii_lowercase())
+        .filter_map(|entry| entry.ok())
+        .map(|(name, _)| name)
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+/// Remove a surrounding code block with an optional language, like `` ```rhai ... ``` ``
+fn strip_code_block(text: &str) -> &str {
+    let text = text.trim();
+
+    match text
+        .strip_prefix("```")
+        .and_then(|text| text.strip_suffix("```"))
+    {
+        // the first line names the language, unless the code starts on it
+        Some(code) => match code.split_once('\n') {
+            Some((language, code)) if !language.contains(' ') => code,
+            _ => code,
+        },
+        None => text,
+    }
+}
+
+/// Lowercase a command name and collapse whitespace, so `Faq  Install` becomes `faq install`
+pub fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_ascii_lowercase()
+}
+
+/// Check whether a name is taken by a built-in command
+fn is_builtin(ctx: Context<'_>, name: &str) -> bool {
+    ctx.framework()
+        .options
+        .commands
+        .iter()
+        .any(|c| c.aliases.contains(&name) || c.name == name)
+}
+
+/// Check whether a name is a command, optionally followed by an entry name,
+/// that isn't taken by a built-in command
+fn is_valid_command_name(ctx: Context<'_>, name: &str) -> bool {
+    let words: Vec<&str> = name.split_whitespace().collect();
+
+    !words.is_empty()
+        && words.len() <= 2
+        && !is_builtin(ctx, words[0])
+        && !has_reserved_character(name)
+}
+
+/// Check a response of the given kind like `setcommand` does,
+/// returning what is wrong with it otherwise
+fn check_response(kind: ResponseKind, response: &str) -> Result<(), String> {
+    match kind {
+        ResponseKind::Text => Ok(()),
+        ResponseKind::Script => {
+            script::check(response).map_err(|e| format!("Invalid script: {}", e))
+        }
+        ResponseKind::Embed => RichResponse::parse(response)
+            .map(|_| ())
+            .map_err(|e| format!("Invalid embed: {}", e)),
+    }
+}
+
+/// Check whether a name is a command or the command of a named entry,
+/// which an alias of that name would shadow
+fn is_command_name(store: &Store, name: &str) -> Result<bool, Error> {
+    if store.contains::<CustomCommand>(name)? {
+        return Ok(true);
+    }
+
+    Ok(store
+        .scan::<CustomCommand>(&format!("{} ", name))
+        .next()
+        .is_some())
+}
+
+/// Check whether a name contains `/`, which separates the name from the number in revision keys
+fn has_reserved_character(name: &str) -> bool {
+    name.contains('/')
+}
+
+/// Get the history of a command, or of an alias if there is no command with that name
+fn find_revisions(store: &Store, name: &str) -> Result<Option<History>, Error> {
+    for target in [RevisionTarget::Command, RevisionTarget::Alias] {
+        let revisions = store.revisions(target, name)?;
+
+        if !revisions.is_empty() {
+            return Ok(Some((target, revisions)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Get the kinds before and after the revision at `index` if it changed the kind of a command.
+/// Removals don't change the kind, revisions before the first one are text.
+fn kind_change(
+    revisions: &[(u32, Revision)],
+    index: usize,
+) -> Option<(ResponseKind, ResponseKind)> {
+    let after = &revisions[index].1;
+    after.content.as_ref()?;
+
+    let before = revisions[..index]
+        .iter()
+        .rev()
+        .find(|(_, revision)| revision.content.is_some())
+        .map_or(ResponseKind::Text, |(_, revision)| revision.kind);
+
+    (before != after.kind).then_some((before, after.kind))
+}
+
+/// Record a change to a command or alias in its history.
+/// If there is no history yet, the state before the change is recorded first so it can be restored.
+fn record_revision(
+    store: &Store,
+    target: RevisionTarget,
+    name: &str,
+    previous: Option<Revision>,
+    content: Option<String>,
+    kind: ResponseKind,
+    author: UserId,
+) -> Result<(), Error> {
+    if let Some(previous) = previous {
+        if store.revisions(target, name)?.is_empty() {
+            store.push_revision(target, name, &previous)?;
+        }
+    }
+
+    store.push_revision(
+        target,
+        name,
+        &Revision {
+            content,
+            kind,
+            author: Some(author),
+            timestamp: Some(Utc::now()),
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Create a command with a new response, keeping the metadata of the previous version
+fn updated_command(
+    previous: Option<CustomCommand>,
+    response: String,
+    author: UserId,
+) -> CustomCommand {
+    match previous {
+        Some(previous) => CustomCommand {
+            response,
+            updated_by: Some(author),
+            updated_at: Some(Utc::now()),
+            ..previous
+        },
+        None => CustomCommand::new(response, author),
+    }
+}
+
+fn command_revision(command: &CustomCommand) -> Revision {
+    Revision {
+        content: Some(command.response.clone()),
+        kind: command.kind,
+        author: command.updated_by,
+        timestamp: command.updated_at,
+    }
+}
+
+fn alias_revision(alias: &Alias) -> Revision {
+    Revision {
+        content: Some(alias.command.clone()),
+        kind: ResponseKind::Text,
+        author: None,
+        timestamp: None,
+    }
+}
+
+/// Send a paginated overview of all custom commands, optionally filtered by a lowercase query
+async fn overview(ctx: Context<'_>, query: Option<String>) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+    let prefix = ctx.data().prefix(ctx.guild_id());
+
+    let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+
+    for entry in store.scan::<Alias>("") {
+        let (name, _) = entry?;
+
+        if let Ok(command) = resolve_alias(&store, &name) {
+            aliases.entry(command).or_default().push(name);
+        }
+    }
+
+    let mut entries = Vec::new();
+
+    for entry in store.scan::<CustomCommand>("") {
+        let (name, command) = entry?;
+        let aliases = aliases.remove(&name).unwrap_or_default();
+
+        if let Some(query) = &query {
+            let matches = name.contains(query.as_str())
+                || aliases.iter().any(|alias| alias.contains(query.as_str()))
+                || command.response.to_lowercase().contains(query.as_str());
+
+            if !matches {
+                continue;
+            }
+        }
+
+        let mut entry = format!("**{}{}**", prefix, name);
+
+        if !aliases.is_empty() {
+            entry.push_str(&format!(" (aliases: {})", aliases.join(", ")));
+        }
+
+        entry.push_str(&format!(
+            "\n> {}",
+            preview(&command.response, PREVIEW_LENGTH)
+        ));
+        entries.push(entry);
+    }
+
+    if entries.is_empty() {
+        say!(ctx, "No custom commands found");
+        return Ok(());
+    }
+
+    let pages: Vec<String> = entries
+        .chunks(ENTRIES_PER_PAGE)
+        .map(|page| page.join("\n\n"))
+        .collect();
+    let pages: Vec<&str> = pages.iter().map(String::as_str).collect();
+
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restrict_adds_and_removes_ids() {
+        let mut restriction = None;
+
+        assert_eq!(
+            restrict_id(&mut restriction, RestrictAction::Allow, 1),
+            Ok(())
+        );
+        assert_eq!(
+            restrict_id(&mut restriction, RestrictAction::Allow, 2),
+            Ok(())
+        );
+        assert_eq!(
+            restrict_id(&mut restriction, RestrictAction::Allow, 2),
+            Ok(())
+        );
+        assert_eq!(
+            restriction,
+            Some(Restriction {
+                mode: RestrictionMode::Allow,
+                ids: vec![1, 2],
+            })
+        );
+
+        assert_eq!(
+            restrict_id(&mut restriction, RestrictAction::Remove, 1),
+            Ok(())
+        );
+        assert_eq!(
+            restrict_id(&mut restriction, RestrictAction::Remove, 2),
+            Ok(())
+        );
+        assert_eq!(restriction, None);
+    }
+
+    #[test]
+    fn restrict_rejects_the_other_mode() {
+        let mut restriction = None;
+
+        assert_eq!(
+            restrict_id(&mut restriction, RestrictAction::Deny, 1),
+            Ok(())
+        );
+        assert_eq!(
+            restrict_id(&mut restriction, RestrictAction::Allow, 2),
+            Err(RestrictionMode::Deny)
+        );
+        assert_eq!(
+            restriction.map(|restriction| restriction.ids),
+            Some(vec![1])
+        );
+    }
+
+    #[test]
+    fn restrict_clear() {
+        let mut restriction = None;
+
+        assert_eq!(
+            restrict_id(&mut restriction, RestrictAction::Deny, 1),
+            Ok(())
+        );
+        assert_eq!(
+            restrict_id(&mut restriction, RestrictAction::Clear, 0),
+            Ok(())
+        );
+        assert_eq!(restriction, None);
+    }
+}
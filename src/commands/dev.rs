@@ -1,28 +1,64 @@
-use crate::{check_output, say, Context};
+use crate::diagnostics;
+use crate::permissions::{owner, shell};
+use crate::utils::preview;
+use crate::{check_output, Context};
 use anyhow::Error;
 
+/// Longest detail shown for a single check
+const DETAIL_LENGTH: usize = 150;
+
 /// Buttons to register slash commands
-#[poise::command(prefix_command, owners_only, hide_in_help)]
+#[poise::command(prefix_command, hide_in_help, check = "owner")]
 pub async fn register(ctx: Context<'_>) -> Result<(), Error> {
     poise::builtins::register_application_commands_buttons(ctx).await?;
     Ok(())
 }
 
 /// Run shell commands. Dangerous.
-#[poise::command(prefix_command, owners_only, hide_in_help)]
+#[poise::command(prefix_command, hide_in_help, check = "shell")]
 pub async fn bash(
     ctx: Context<'_>,
     #[description = "Command to run"]
     #[rest]
     command: String,
 ) -> Result<(), Error> {
-    // pixelagent007
-    if ctx.author().id.0 != 487247155741065229 {
-        say!(ctx, "Go away gooberoo, this is dangerous.");
+    check_output!("bash", ["-c", &command], "execute command", ctx);
+    Ok(())
+}
 
-        return Ok(());
-    }
+/// Check that the tools, pack repository, database and permissions the bot needs are set up
+#[poise::command(slash_command, prefix_command, check = "owner", ephemeral)]
+pub async fn doctor(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_or_broadcast().await?;
+
+    let guilds: Vec<_> = ctx.guild_id().into_iter().collect();
+    let checks = diagnostics::run(ctx.serenity_context(), ctx.data(), &guilds).await;
+
+    let report = checks
+        .iter()
+        .map(|check| {
+            format!(
+                "{} **{}**: {}",
+                if check.passed { "✅" } else { "⚠️" },
+                check.name,
+                preview(&check.detail, DETAIL_LENGTH)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let failed = checks.iter().filter(|check| !check.passed).count();
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.title(match failed {
+                0 => "Everything looks fine".to_string(),
+                failed => format!("{} checks failed", failed),
+            })
+            .description(report)
+        })
+    })
+    .await?;
 
-    check_output!("bash", ["-c", &command], "execute command", ctx);
     Ok(())
 }
@@ -1,13 +1,16 @@
+use crate::config::Config;
+use crate::confirm::confirm;
+use crate::permissions::git_write;
 use crate::{check_output, Context};
 use anyhow::Error;
 use chrono::Utc;
+use std::process::Command;
 
-// idk what clippy is smoking here, this isn't dead code
-#[allow(dead_code)]
-const CURRENT_ITERATION: &str = "v2";
+/// Most lines of git output shown in a confirmation prompt
+const MAX_PROMPT_LINES: usize = 20;
 
 /// Commit all current changes.
-#[poise::command(slash_command, prefix_command, owners_only)]
+#[poise::command(slash_command, prefix_command, check = "git_write")]
 pub async fn commit(
     ctx: Context<'_>,
     #[description = "Commit message. Use conventional commmits. See https://www.conventionalcommits.org/en/v1.0.0/"]
@@ -21,21 +24,46 @@ pub async fn commit(
 }
 
 /// Discard all current changes. Beware.
-#[poise::command(slash_command, prefix_command, owners_only)]
+#[poise::command(slash_command, prefix_command, check = "git_write")]
 pub async fn reset(ctx: Context<'_>) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let branch = &config.git.branch;
+
+    let changes = git_lines(&config, &["status", "--short", "--untracked-files=all"]);
+    let commits = unpushed_commits(&config);
+
+    let mut prompt = format!(
+        "Reset the pack to `origin/{}`? This can't be undone.",
+        branch
+    );
+
+    match changes.is_empty() {
+        true => prompt.push_str("\nThere are no uncommitted changes."),
+        false => prompt.push_str(&format!(
+            "\nThese changes are discarded:\n{}",
+            code_block(&changes)
+        )),
+    }
+
+    if !commits.is_empty() {
+        prompt.push_str(&format!(
+            "\nThese commits are lost:\n{}",
+            code_block(&commits)
+        ));
+    }
+
+    if !confirm(ctx, &prompt).await? {
+        return Ok(());
+    }
+
     check_output!("git", ["pull", "origin"], "fetch latest changes", ctx);
     check_output!("git", ["clean", "-fd"], "clean working directory", ctx);
 
-    check_output!(
-        "git",
-        ["checkout", CURRENT_ITERATION],
-        "checkout remote state",
-        ctx
-    );
+    check_output!("git", ["checkout", branch], "checkout remote state", ctx);
 
     check_output!(
         "git",
-        ["reset", "--hard", &format!("origin/{}", CURRENT_ITERATION)],
+        ["reset", "--hard", &format!("origin/{}", branch)],
         "reset to remote latest state",
         ctx
     );
@@ -44,13 +72,33 @@ pub async fn reset(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Open a pull request with current changes. Make sure to commit beforehand.
-#[poise::command(slash_command, prefix_command, owners_only)]
+#[poise::command(slash_command, prefix_command, check = "git_write")]
 pub async fn pull_request(
     ctx: Context<'_>,
     #[description = "The title of the pull request. Visible in the changelog."]
     #[rest]
     title: String,
 ) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let commits = unpushed_commits(&config);
+
+    let mut prompt = format!(
+        "Open a pull request titled `{}` against `{}`?",
+        title, config.git.branch
+    );
+
+    match commits.is_empty() {
+        true => prompt.push_str("\nThere are no commits to include, did you commit?"),
+        false => prompt.push_str(&format!(
+            "\nThese commits are included:\n{}",
+            code_block(&commits)
+        )),
+    }
+
+    if !confirm(ctx, &prompt).await? {
+        return Ok(());
+    }
+
     let branch = format!("pull-request-{}", Utc::now().timestamp_millis());
 
     check_output!(
@@ -84,7 +132,7 @@ pub async fn pull_request(
             &title,
             "--fill",
             "--base",
-            CURRENT_ITERATION,
+            &config.git.branch,
         ],
         "create pull request",
         ctx
@@ -92,3 +140,47 @@ pub async fn pull_request(
 
     Ok(())
 }
+
+/// Lines printed by a git command in the pack repository, empty if it failed
+fn git_lines(config: &Config, args: &[&str]) -> Vec<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(&config.packwiz.repo_path)
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// One line summaries of the commits that aren't on the remote yet
+fn unpushed_commits(config: &Config) -> Vec<String> {
+    git_lines(
+        config,
+        &[
+            "log",
+            "--oneline",
+            &format!("origin/{}..HEAD", config.git.branch),
+        ],
+    )
+}
+
+/// Show lines in a code block, leaving out the ones that don't fit in a prompt
+fn code_block(lines: &[String]) -> String {
+    let mut block = lines
+        .iter()
+        .take(MAX_PROMPT_LINES)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if lines.len() > MAX_PROMPT_LINES {
+        block.push_str(&format!("\n…and {} more", lines.len() - MAX_PROMPT_LINES));
+    }
+
+    format!("```\n{}\n```", block)
+}
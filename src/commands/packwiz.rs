@@ -1,10 +1,9 @@
-use crate::utils::fatal;
-use crate::{check_output, say, Context};
+use crate::confirm::confirm;
+use crate::permissions::packwiz_write;
+use crate::{audit, check_output, say, Context};
 use anyhow::Error;
-use std::env::var;
 use std::process::Command;
 
-const DISALLOWED_SUBCOMMANDS: [&str; 4] = ["init", "completion", "utils", "serve"];
 const HELP_SUBCOMMANDS: [&str; 2] = ["help", "--help"];
 
 const HELP_1: &str = r###"
@@ -48,7 +47,7 @@ Use "packwiz [command] --help" for more information about a command.
 
 /// Run packwiz commands.  
 /// Should be safe to run outside a container, but be cautious. Might allow RCE.
-#[poise::command(slash_command, prefix_command, owners_only)]
+#[poise::command(slash_command, prefix_command, check = "packwiz_write")]
 pub async fn packwiz(
     ctx: Context<'_>,
     #[description = "Arguments to pass to packwiz"]
@@ -57,10 +56,9 @@ pub async fn packwiz(
 ) -> Result<(), Error> {
     let mut cmd = Command::new("packwiz");
 
-    let cwd = var("PACKWIZ_REPO_PATH")
-        .unwrap_or_else(|e| fatal("PACKWIZ_REPO_PATH not found in env!", e));
+    let config = ctx.data().config();
 
-    cmd.current_dir(cwd);
+    cmd.current_dir(&config.packwiz.repo_path);
 
     if let Some(args) = args {
         let command: String;
@@ -74,17 +72,22 @@ pub async fn packwiz(
                     let args = args
                         .strip_prefix("bulkinstall ")
                         .unwrap_or_else(|| unreachable!());
-                    let lines: Vec<&str> = args.split('\n').collect();
+                    let lines: Vec<&str> = args
+                        .split('\n')
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .collect();
+
+                    let prompt = format!(
+                        "Install these {} mods?\n```\n{}\n```",
+                        lines.len(),
+                        lines.join("\n")
+                    );
+
+                    if !confirm(ctx, &prompt).await? {
+                        return Ok(());
+                    }
 
                     for line in lines {
-                        if line.starts_with('#') {
-                            continue;
-                        }
-
-                        if line.is_empty() {
-                            continue;
-                        }
-
                         if line.contains("modrinth.com") {
                             check_output!(
                                 "packwiz",
@@ -123,13 +126,21 @@ pub async fn packwiz(
             return Ok(());
         }
 
-        if DISALLOWED_SUBCOMMANDS.contains(&command) {
+        if config
+            .packwiz
+            .disallowed_subcommands
+            .iter()
+            .any(|disallowed| disallowed == command)
+        {
             say!(ctx, "That command is disabled");
             return Ok(());
         }
     }
 
-    match cmd.output() {
+    let output = cmd.output();
+    audit::process_exited(ctx, output.as_ref().ok().map(|output| output.status)).await;
+
+    match output {
         Ok(output) => {
             let stdout = String::from_utf8_lossy(&output.stdout);
             let stderr = String::from_utf8_lossy(&output.stderr);
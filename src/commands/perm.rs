@@ -0,0 +1,161 @@
+use crate::permissions::{grants_store, is_owner, perms_manage, Capability};
+use crate::storage::Grants;
+use crate::{say, Context};
+use anyhow::Error;
+use poise::serenity_prelude::{Mentionable, Role, User};
+
+/// Manage who may use commands that need a permission
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("grant", "revoke", "list")
+)]
+pub async fn perm(ctx: Context<'_>) -> Result<(), Error> {
+    overview(ctx).await
+}
+
+/// Lists who has each permission
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    overview(ctx).await
+}
+
+/// Grants a permission to a user or role
+#[poise::command(slash_command, prefix_command, guild_only, check = "perms_manage")]
+pub async fn grant(
+    ctx: Context<'_>,
+    #[description = "Permission to grant"] capability: Capability,
+    #[description = "User to grant it to"] user: Option<User>,
+    #[description = "Role to grant it to"] role: Option<Role>,
+) -> Result<(), Error> {
+    change(ctx, capability, user, role, true).await
+}
+
+/// Revokes a permission from a user or role
+#[poise::command(slash_command, prefix_command, guild_only, check = "perms_manage")]
+pub async fn revoke(
+    ctx: Context<'_>,
+    #[description = "Permission to revoke"] capability: Capability,
+    #[description = "User to revoke it from"] user: Option<User>,
+    #[description = "Role to revoke it from"] role: Option<Role>,
+) -> Result<(), Error> {
+    change(ctx, capability, user, role, false).await
+}
+
+async fn change(
+    ctx: Context<'_>,
+    capability: Capability,
+    user: Option<User>,
+    role: Option<Role>,
+    granted: bool,
+) -> Result<(), Error> {
+    if user.is_none() && role.is_none() {
+        say!(ctx, "Pick a user or a role");
+        return Ok(());
+    }
+
+    if capability.is_global() && !is_owner(ctx) {
+        say!(
+            ctx,
+            "Only owners of the bot can change `{}`, it applies to every server",
+            capability.name()
+        );
+        return Ok(());
+    }
+
+    let store = match grants_store(ctx, capability)? {
+        Some(store) => store,
+        None => {
+            say!(ctx, "This command can only be used in a guild");
+            return Ok(());
+        }
+    };
+
+    store.upsert::<Grants>(capability.key(), |grants| {
+        if let Some(user) = &user {
+            toggle(&mut grants.users, user.id, granted);
+        }
+
+        if let Some(role) = &role {
+            toggle(&mut grants.roles, role.id, granted);
+        }
+    })?;
+
+    let targets: Vec<String> = user
+        .iter()
+        .map(|user| user.mention().to_string())
+        .chain(role.iter().map(|role| role.mention().to_string()))
+        .collect();
+
+    let (verb, preposition) = match granted {
+        true => ("Granted", "to"),
+        false => ("Revoked", "from"),
+    };
+
+    ctx.send(|m| {
+        m.content(format!(
+            "{} `{}` {} {}",
+            verb,
+            capability.name(),
+            preposition,
+            targets.join(" and ")
+        ))
+        .allowed_mentions(|a| a.empty_parse())
+    })
+    .await?;
+
+    Ok(())
+}
+
+fn toggle<T: PartialEq>(ids: &mut Vec<T>, id: T, granted: bool) {
+    let present = ids.contains(&id);
+
+    if granted && !present {
+        ids.push(id);
+    } else if !granted {
+        ids.retain(|existing| *existing != id);
+    }
+}
+
+async fn overview(ctx: Context<'_>) -> Result<(), Error> {
+    let mut lines = Vec::new();
+
+    for capability in Capability::ALL {
+        let grants = match grants_store(ctx, capability)? {
+            Some(store) => store.get::<Grants>(capability.key())?.unwrap_or_default(),
+            None => Grants::default(),
+        };
+
+        let holders: Vec<String> = grants
+            .users
+            .iter()
+            .map(|user| user.mention().to_string())
+            .chain(grants.roles.iter().map(|role| role.mention().to_string()))
+            .collect();
+
+        let holders = match (holders.is_empty(), capability) {
+            (true, Capability::Shell) => "nobody".to_string(),
+            (true, _) => "bot owners only".to_string(),
+            (false, _) => holders.join(", "),
+        };
+
+        lines.push(format!(
+            "**{}**{}: {}",
+            capability.name(),
+            match capability.is_global() {
+                true => " (every server)",
+                false => "",
+            },
+            holders
+        ));
+    }
+
+    ctx.send(|m| {
+        m.content(lines.join("\n"))
+            .allowed_mentions(|a| a.empty_parse())
+    })
+    .await?;
+
+    Ok(())
+}
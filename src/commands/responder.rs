@@ -0,0 +1,260 @@
+use super::custom::{
+    complete_names, describe_restriction, normalize_name, restrict_id, RestrictAction,
+    ENTRIES_PER_PAGE, PREVIEW_LENGTH,
+};
+use crate::permissions::commands_manage;
+use crate::responder::compile;
+use crate::storage::{AutoResponder, Cooldown, CooldownScope, TriggerKind};
+use crate::utils::{guild_store, preview};
+use crate::{say, Context};
+use anyhow::Error;
+use chrono::Utc;
+use poise::serenity_prelude::GuildChannel;
+
+/// Cooldown of new auto-responders, so busy channels don't get the same reply over and over
+const DEFAULT_COOLDOWN: Cooldown = Cooldown {
+    seconds: 60,
+    scope: CooldownScope::Channel,
+};
+
+/// Manage auto-responders, which reply to messages containing a keyword or matching a regex
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("add", "remove", "list", "cooldown", "channel")
+)]
+pub async fn responder(ctx: Context<'_>) -> Result<(), Error> {
+    overview(ctx).await
+}
+
+/// Lists all auto-responders
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    overview(ctx).await
+}
+
+/// Creates or updates an auto-responder.
+///
+/// Responses support the same placeholders as custom commands.
+/// Regexes are case-sensitive unless they start with `(?i)`.
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "Responder name"]
+    #[autocomplete = "autocomplete_responder"]
+    name: String,
+    #[description = "How the pattern is matched"] kind: TriggerKind,
+    #[description = "Keyword or regex to look for"] pattern: String,
+    #[description = "Response, supports the same placeholders as custom commands"]
+    #[rest]
+    response: String,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+    let name = normalize_name(&name);
+
+    if name.is_empty() || name.contains(' ') {
+        say!(ctx, "Responder names must be a single word");
+        return Ok(());
+    }
+
+    if pattern.trim().is_empty() {
+        say!(ctx, "The pattern can't be empty");
+        return Ok(());
+    }
+
+    if kind == TriggerKind::Regex {
+        if let Err(e) = compile(&pattern) {
+            say!(ctx, "Invalid regex: {}", e);
+            return Ok(());
+        }
+    }
+
+    let responder = match store.get::<AutoResponder>(&name)? {
+        Some(existing) => AutoResponder {
+            kind,
+            pattern,
+            response,
+            ..existing
+        },
+        None => AutoResponder {
+            kind,
+            pattern,
+            response,
+            author: Some(ctx.author().id),
+            created_at: Some(Utc::now()),
+            cooldown: Some(DEFAULT_COOLDOWN),
+            channels: None,
+        },
+    };
+
+    match store.insert(&name, &responder)? {
+        Some(_) => say!(ctx, "Responder `{}` updated", name),
+        None => say!(ctx, "Responder `{}` created", name),
+    }
+
+    Ok(())
+}
+
+/// Removes an auto-responder
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Responder name"]
+    #[autocomplete = "autocomplete_responder"]
+    name: String,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+    let name = normalize_name(&name);
+
+    match store.remove::<AutoResponder>(&name)? {
+        Some(_) => say!(ctx, "Responder `{}` removed", name),
+        None => say!(ctx, "There is no responder named `{}`", name),
+    }
+
+    Ok(())
+}
+
+/// Sets how long an auto-responder stays quiet after it replied
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn cooldown(
+    ctx: Context<'_>,
+    #[description = "Responder name"]
+    #[autocomplete = "autocomplete_responder"]
+    name: String,
+    #[description = "Cooldown in seconds, 0 to remove it"] seconds: u64,
+    #[description = "Who the cooldown applies to, defaults to each channel"] scope: Option<
+        CooldownScope,
+    >,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+    let name = normalize_name(&name);
+
+    if seconds > Cooldown::MAX_SECONDS {
+        say!(
+            ctx,
+            "Cooldowns can be at most {} seconds, a week",
+            Cooldown::MAX_SECONDS
+        );
+        return Ok(());
+    }
+
+    let cooldown = match seconds {
+        0 => None,
+        seconds => Some(Cooldown {
+            seconds,
+            scope: scope.unwrap_or(CooldownScope::Channel),
+        }),
+    };
+
+    let updated = store.update::<AutoResponder>(&name, |responder| {
+        responder.cooldown = cooldown.clone();
+    })?;
+
+    match (updated, cooldown) {
+        (None, _) => say!(ctx, "There is no responder named `{}`", name),
+        (Some(_), None) => say!(ctx, "Removed the cooldown of `{}`", name),
+        (Some(_), Some(cooldown)) => say!(
+            ctx,
+            "`{}` replies at most once every {} seconds per {}",
+            name,
+            cooldown.seconds,
+            cooldown.scope.name()
+        ),
+    }
+
+    Ok(())
+}
+
+/// Restricts which channels an auto-responder replies in.
+///
+/// Without a channel, the current restriction is shown, or cleared.
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn channel(
+    ctx: Context<'_>,
+    #[description = "Responder name"]
+    #[autocomplete = "autocomplete_responder"]
+    name: String,
+    #[description = "Add to the allowlist or denylist, or remove from it"] action: RestrictAction,
+    #[description = "Channel to add or remove"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+    let name = normalize_name(&name);
+
+    let mut conflict = None;
+
+    let updated = store.update::<AutoResponder>(&name, |responder| match &channel {
+        Some(channel) => {
+            if let Err(mode) = restrict_id(&mut responder.channels, action, channel.id) {
+                conflict = Some(mode);
+            }
+        }
+        None if action == RestrictAction::Clear => responder.channels = None,
+        None => (),
+    })?;
+
+    let responder = match updated {
+        Some(responder) => responder,
+        None => {
+            say!(ctx, "There is no responder named `{}`", name);
+            return Ok(());
+        }
+    };
+
+    if let Some(mode) = conflict {
+        say!(
+            ctx,
+            "Channels of `{}` are already restricted by a {} list, remove or clear them first",
+            name,
+            mode.name()
+        );
+        return Ok(());
+    }
+
+    say!(
+        ctx,
+        "Channels of `{}`: {}",
+        name,
+        describe_restriction(&responder.channels)
+    );
+
+    Ok(())
+}
+
+/// Suggest names of existing auto-responders
+async fn autocomplete_responder(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    complete_names::<AutoResponder>(ctx, partial)
+}
+
+async fn overview(ctx: Context<'_>) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let mut entries = Vec::new();
+
+    for entry in store.scan::<AutoResponder>("") {
+        let (name, responder) = entry?;
+
+        entries.push(format!(
+            "**{}** ({} `{}`)\n> {}",
+            name,
+            responder.kind.name(),
+            preview(&responder.pattern, PREVIEW_LENGTH),
+            preview(&responder.response, PREVIEW_LENGTH)
+        ));
+    }
+
+    if entries.is_empty() {
+        say!(ctx, "No auto-responders found");
+        return Ok(());
+    }
+
+    let pages: Vec<String> = entries
+        .chunks(ENTRIES_PER_PAGE)
+        .map(|page| page.join("\n\n"))
+        .collect();
+    let pages: Vec<&str> = pages.iter().map(String::as_str).collect();
+
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use super::custom::{complete_names, ENTRIES_PER_PAGE, PREVIEW_LENGTH};
+use crate::permissions::commands_manage;
+use crate::storage::Variable;
+use crate::template::variable_name;
+use crate::utils::{guild_store, preview};
+use crate::{say, Context};
+use anyhow::Error;
+
+/// Manage variables and counters used in custom commands with `{var:name}` and `{count:name}`
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("set", "remove", "list")
+)]
+pub async fn var(ctx: Context<'_>) -> Result<(), Error> {
+    overview(ctx).await
+}
+
+/// Lists all variables and counters with their values
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    overview(ctx).await
+}
+
+/// Sets the value of a variable or counter
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn set(
+    ctx: Context<'_>,
+    #[description = "Variable name"]
+    #[autocomplete = "autocomplete_variable"]
+    name: String,
+    #[description = "New value, a number for counters"]
+    #[rest]
+    value: String,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+    let name = variable_name(&name);
+
+    if name.is_empty() || name.contains(['{', '}', ':']) {
+        say!(ctx, "Invalid name");
+        return Ok(());
+    }
+
+    let previous = store.insert(&name, &Variable { value })?;
+
+    match previous {
+        Some(_) => say!(ctx, "Variable `{}` updated", name),
+        None => say!(ctx, "Variable `{}` created", name),
+    }
+
+    Ok(())
+}
+
+/// Removes a variable or counter
+#[poise::command(slash_command, prefix_command, guild_only, check = "commands_manage")]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Variable name"]
+    #[autocomplete = "autocomplete_variable"]
+    name: String,
+) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+    let name = variable_name(&name);
+
+    match store.remove::<Variable>(&name)? {
+        Some(_) => say!(ctx, "Variable `{}` removed", name),
+        None => say!(ctx, "There is no variable named `{}`", name),
+    }
+
+    Ok(())
+}
+
+/// Suggest names of existing variables
+async fn autocomplete_variable(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    complete_names::<Variable>(ctx, partial)
+}
+
+async fn overview(ctx: Context<'_>) -> Result<(), Error> {
+    let store = guild_store(ctx)?;
+
+    let mut entries = Vec::new();
+
+    for entry in store.scan::<Variable>("") {
+        let (name, variable) = entry?;
+
+        entries.push(format!(
+            "**{}**: {}",
+            name,
+            preview(&variable.value, PREVIEW_LENGTH)
+        ));
+    }
+
+    if entries.is_empty() {
+        say!(ctx, "No variables found");
+        return Ok(());
+    }
+
+    let pages: Vec<String> = entries
+        .chunks(ENTRIES_PER_PAGE)
+        .map(|page| page.join("\n"))
+        .collect();
+    let pages: Vec<&str> = pages.iter().map(String::as_str).collect();
+
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
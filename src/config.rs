@@ -0,0 +1,240 @@
+use anyhow::{anyhow, bail, Context as _, Error};
+use poise::serenity_prelude::{GuildId, UserId};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Used when `CONFIG_PATH` isn't set, it's fine if this file doesn't exist
+const DEFAULT_PATH: &str = "config.toml";
+
+/// Settings of the bot, read from a TOML file.
+/// Every setting can be overridden with the environment variable named in its comment.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Prefix of text commands, `PREFIX`
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// Users allowed to do anything, `OWNERS` as comma separated ids
+    #[serde(default)]
+    pub owners: HashSet<UserId>,
+    /// Directory of the database, `DB_PATH`. Changes need a restart.
+    #[serde(default)]
+    pub db_path: PathBuf,
+    /// Guild to move data from before per-guild storage into, `LEGACY_GUILD_ID`.
+    /// Changes need a restart.
+    #[serde(default)]
+    pub legacy_guild_id: Option<GuildId>,
+    #[serde(default)]
+    pub git: GitConfig,
+    #[serde(default)]
+    pub packwiz: PackwizConfig,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GitConfig {
+    /// Branch of the current iteration of the pack, `GIT_BRANCH`
+    #[serde(default = "default_branch")]
+    pub branch: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PackwizConfig {
+    /// Checkout of the pack repository, `PACKWIZ_REPO_PATH`
+    #[serde(default)]
+    pub repo_path: PathBuf,
+    /// Subcommands that can't be run with the packwiz command,
+    /// `PACKWIZ_DISALLOWED_SUBCOMMANDS` as comma separated names
+    #[serde(default = "default_disallowed_subcommands")]
+    pub disallowed_subcommands: Vec<String>,
+}
+
+fn default_prefix() -> String {
+    "!".to_string()
+}
+
+fn default_branch() -> String {
+    "v2".to_string()
+}
+
+fn default_disallowed_subcommands() -> Vec<String> {
+    ["init", "completion", "utils", "serve"]
+        .map(String::from)
+        .to_vec()
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            branch: default_branch(),
+        }
+    }
+}
+
+impl Default for PackwizConfig {
+    fn default() -> Self {
+        Self {
+            repo_path: PathBuf::new(),
+            disallowed_subcommands: default_disallowed_subcommands(),
+        }
+    }
+}
+
+impl Config {
+    /// Read the config from `CONFIG_PATH` or `config.toml`, apply the environment and validate it
+    pub fn load() -> Result<Self, Error> {
+        let (path, required) = match env_var("CONFIG_PATH") {
+            Some(path) => (PathBuf::from(path), true),
+            None => (PathBuf::from(DEFAULT_PATH), false),
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound && !required => String::new(),
+            Err(e) => {
+                return Err(Error::from(e).context(format!("Couldn't read {}", path.display())))
+            }
+        };
+
+        let mut config: Config =
+            toml::from_str(&text).with_context(|| format!("Couldn't parse {}", path.display()))?;
+
+        config.apply_env()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), Error> {
+        if let Some(prefix) = env_var("PREFIX") {
+            self.prefix = prefix;
+        }
+
+        if let Some(owners) = env_var("OWNERS") {
+            self.owners = split_list(&owners)
+                .map(|id| {
+                    id.parse()
+                        .map(UserId)
+                        .map_err(|_| anyhow!("OWNERS: `{}` is not a valid user id", id))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+
+        if let Some(path) = env_var("DB_PATH") {
+            self.db_path = path.into();
+        }
+
+        if let Some(id) = env_var("LEGACY_GUILD_ID") {
+            let id = id
+                .parse()
+                .map_err(|_| anyhow!("LEGACY_GUILD_ID: `{}` is not a valid guild id", id))?;
+            self.legacy_guild_id = Some(GuildId(id));
+        }
+
+        if let Some(branch) = env_var("GIT_BRANCH") {
+            self.git.branch = branch;
+        }
+
+        if let Some(path) = env_var("PACKWIZ_REPO_PATH") {
+            self.packwiz.repo_path = path.into();
+        }
+
+        if let Some(subcommands) = env_var("PACKWIZ_DISALLOWED_SUBCOMMANDS") {
+            self.packwiz.disallowed_subcommands =
+                split_list(&subcommands).map(String::from).collect();
+        }
+
+        Ok(())
+    }
+
+    /// Check all settings, reporting every problem at once
+    fn validate(&self) -> Result<(), Error> {
+        let mut problems = Vec::new();
+
+        if self.prefix.is_empty() || self.prefix.contains(char::is_whitespace) {
+            problems.push("prefix must be non-empty and can't contain spaces".to_string());
+        }
+
+        if self.owners.is_empty() {
+            problems.push("owners is empty, nobody could manage the bot".to_string());
+        }
+
+        if self.db_path.as_os_str().is_empty() {
+            problems.push("db_path is not set".to_string());
+        }
+
+        if self.git.branch.trim().is_empty() {
+            problems.push("git.branch can't be empty".to_string());
+        }
+
+        if !problems.is_empty() {
+            bail!("invalid configuration:\n- {}", problems.join("\n- "));
+        }
+
+        Ok(())
+    }
+
+    /// Settings that differ from `other` but only take effect after a restart
+    pub fn restart_required(&self, other: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+
+        if self.db_path != other.db_path {
+            changed.push("db_path");
+        }
+
+        if self.legacy_guild_id != other.legacy_guild_id {
+            changed.push("legacy_guild_id");
+        }
+
+        changed
+    }
+}
+
+/// Get an environment variable, treating empty values as unset
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Config {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn valid_config() {
+        let config = parse("owners = [1]\ndb_path = \"db\"");
+
+        assert!(config.validate().is_ok());
+        assert_eq!(config.prefix, "!");
+        assert_eq!(config.git.branch, "v2");
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let config = parse("prefix = \"a b\"\n[git]\nbranch = \" \"");
+        let message = config.validate().unwrap_err().to_string();
+
+        for problem in ["prefix", "owners", "db_path", "git.branch"] {
+            assert!(message.contains(problem), "{} not in {}", problem, message);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(toml::from_str::<Config>("owner = [1]").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::utils::truncate;
+use crate::Context;
+use anyhow::Error;
+use poise::serenity_prelude::{ButtonStyle, CollectComponentInteraction, InteractionResponseType};
+use std::time::{Duration, Instant};
+
+/// How long the invoker has to answer a confirmation prompt
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest prompt shown, leaving room for the outcome within the message limit of Discord
+const MAX_PROMPT_LENGTH: usize = 1900;
+
+/// Ask the invoker to confirm an action with buttons.
+/// `prompt` should describe what will happen. Returns whether the action was confirmed,
+/// which is never the case when the prompt times out.
+pub async fn confirm(ctx: Context<'_>, prompt: &str) -> Result<bool, Error> {
+    let prompt = truncate(prompt, MAX_PROMPT_LENGTH);
+
+    let ctx_id = ctx.id();
+    let confirm_id = format!("{}confirm", ctx_id);
+    let cancel_id = format!("{}cancel", ctx_id);
+
+    let reply = ctx
+        .send(|m| {
+            m.content(&prompt)
+                .allowed_mentions(|a| a.empty_parse())
+                .components(|c| {
+                    c.create_action_row(|r| {
+                        r.create_button(|b| {
+                            b.custom_id(&confirm_id)
+                                .label("Confirm")
+                                .style(ButtonStyle::Danger)
+                        })
+                        .create_button(|b| {
+                            b.custom_id(&cancel_id)
+                                .label("Cancel")
+                                .style(ButtonStyle::Secondary)
+                        })
+                    })
+                })
+        })
+        .await?;
+
+    let deadline = Instant::now() + TIMEOUT;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+
+        let press = CollectComponentInteraction::new(ctx)
+            .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+            .timeout(remaining)
+            .await;
+
+        let press = match press {
+            Some(press) => press,
+            None => {
+                reply
+                    .edit(ctx, |m| {
+                        m.content(format!("{}\n\nTimed out, nothing was done.", prompt))
+                            .components(|c| c)
+                    })
+                    .await?;
+
+                return Ok(false);
+            }
+        };
+
+        if press.user.id != ctx.author().id {
+            press
+                .create_interaction_response(ctx, |r| {
+                    r.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|d| {
+                            d.content("Only the person who ran the command can answer this")
+                                .ephemeral(true)
+                        })
+                })
+                .await?;
+
+            continue;
+        }
+
+        let confirmed = press.data.custom_id == confirm_id;
+        let outcome = match confirmed {
+            true => "Confirmed.",
+            false => "Cancelled, nothing was done.",
+        };
+
+        press
+            .create_interaction_response(ctx, |r| {
+                r.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| {
+                        d.content(format!("{}\n\n{}", prompt, outcome))
+                            .components(|c| c)
+                    })
+            })
+            .await?;
+
+        return Ok(confirmed);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::storage::{Cooldown, CooldownScope, FloodLimit};
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Tracked entries are pruned once there are more than this many
+const PRUNE_THRESHOLD: usize = 1024;
+/// How often a single user may get a "did you mean" suggestion
+const SUGGESTION_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Result of checking the guild-wide flood limit for a user
+#[derive(Debug, PartialEq, Eq)]
+pub enum Flood {
+    /// The user may use commands
+    Allowed,
+    /// The user just exceeded the limit
+    Exceeded,
+    /// The user already exceeded the limit and was notified
+    Limited,
+}
+
+/// In-memory state of command cooldowns and flood limits, lost on restart
+#[derive(Default)]
+pub struct Cooldowns {
+    /// When a command may be used again, keyed by guild, command and scope
+    commands: HashMap<(GuildId, String, u64), Instant>,
+    /// When an auto-responder may reply again, keyed like commands
+    responders: HashMap<(GuildId, String, u64), Instant>,
+    /// Recent command uses of each user
+    uses: HashMap<(GuildId, UserId), VecDeque<Instant>>,
+    /// Users that exceeded the flood limit and were notified about it
+    limited: HashSet<(GuildId, UserId)>,
+    /// When each user last got a suggestion for an unknown command
+    suggestions: HashMap<(GuildId, UserId), Instant>,
+}
+
+impl Cooldowns {
+    /// Check whether a command is on cooldown and start the cooldown if it isn't.
+    /// Returns the time left if the command is on cooldown.
+    pub fn check_command(
+        &mut self,
+        guild_id: GuildId,
+        command: &str,
+        cooldown: &Cooldown,
+        user_id: UserId,
+        channel_id: ChannelId,
+    ) -> Option<Duration> {
+        check_cooldown(
+            &mut self.commands,
+            guild_id,
+            command,
+            cooldown,
+            user_id,
+            channel_id,
+        )
+    }
+
+    /// Get the time left on the cooldown of a command without starting it
+    pub fn command_cooldown(
+        &self,
+        guild_id: GuildId,
+        command: &str,
+        cooldown: &Cooldown,
+        user_id: UserId,
+        channel_id: ChannelId,
+    ) -> Option<Duration> {
+        let key = cooldown_key(guild_id, command, cooldown, user_id, channel_id);
+
+        remaining(&self.commands, &key, Instant::now())
+    }
+
+    /// Check whether an auto-responder is on cooldown and start the cooldown if it isn't.
+    /// Returns the time left if the responder is on cooldown.
+    pub fn check_responder(
+        &mut self,
+        guild_id: GuildId,
+        responder: &str,
+        cooldown: &Cooldown,
+        user_id: UserId,
+        channel_id: ChannelId,
+    ) -> Option<Duration> {
+        check_cooldown(
+            &mut self.responders,
+            guild_id,
+            responder,
+            cooldown,
+            user_id,
+            channel_id,
+        )
+    }
+
+    /// Record a command use of a user and check it against the flood limit of the guild
+    pub fn check_flood(&mut self, guild_id: GuildId, user_id: UserId, limit: &FloodLimit) -> Flood {
+        let now = Instant::now();
+        let window = Duration::from_secs(limit.seconds);
+        let key = (guild_id, user_id);
+
+        if self.uses.len() > PRUNE_THRESHOLD {
+            self.uses
+                .retain(|_, uses| uses.back().is_some_and(|last| now - *last < window));
+        }
+
+        let uses = self.uses.entry(key).or_default();
+
+        while uses.front().is_some_and(|first| now - *first >= window) {
+            uses.pop_front();
+        }
+
+        if uses.len() < limit.messages as usize {
+            uses.push_back(now);
+            self.limited.remove(&key);
+
+            return Flood::Allowed;
+        }
+
+        match self.limited.insert(key) {
+            true => Flood::Exceeded,
+            false => Flood::Limited,
+        }
+    }
+
+    /// Check whether a user may get a suggestion for an unknown command and record it if so
+    pub fn check_suggestion(&mut self, guild_id: GuildId, user_id: UserId) -> bool {
+        let now = Instant::now();
+
+        if let Some(last) = self.suggestions.get(&(guild_id, user_id)) {
+            if now - *last < SUGGESTION_COOLDOWN {
+                return false;
+            }
+        }
+
+        if self.suggestions.len() > PRUNE_THRESHOLD {
+            self.suggestions
+                .retain(|_, last| now - *last < SUGGESTION_COOLDOWN);
+        }
+
+        self.suggestions.insert((guild_id, user_id), now);
+
+        true
+    }
+}
+
+/// Check a cooldown in `cooldowns` and start it if it isn't active, returning the time left if it is
+fn check_cooldown(
+    cooldowns: &mut HashMap<(GuildId, String, u64), Instant>,
+    guild_id: GuildId,
+    name: &str,
+    cooldown: &Cooldown,
+    user_id: UserId,
+    channel_id: ChannelId,
+) -> Option<Duration> {
+    let now = Instant::now();
+    let key = cooldown_key(guild_id, name, cooldown, user_id, channel_id);
+
+    if let Some(left) = remaining(cooldowns, &key, now) {
+        return Some(left);
+    }
+
+    if cooldowns.len() > PRUNE_THRESHOLD {
+        cooldowns.retain(|_, until| *until > now);
+    }
+
+    // cooldowns too long for the clock, stored before they were limited, are ignored
+    let until = now
+        .checked_add(Duration::from_secs(cooldown.seconds))
+        .unwrap_or(now);
+    cooldowns.insert(key, until);
+
+    None
+}
+
+fn cooldown_key(
+    guild_id: GuildId,
+    name: &str,
+    cooldown: &Cooldown,
+    user_id: UserId,
+    channel_id: ChannelId,
+) -> (GuildId, String, u64) {
+    let scope = match cooldown.scope {
+        CooldownScope::User => user_id.0,
+        CooldownScope::Channel => channel_id.0,
+        CooldownScope::Guild => 0,
+    };
+
+    (guild_id, name.to_string(), scope)
+}
+
+/// Get the time left until a cooldown ends, if it hasn't yet
+fn remaining(
+    cooldowns: &HashMap<(GuildId, String, u64), Instant>,
+    key: &(GuildId, String, u64),
+    now: Instant,
+) -> Option<Duration> {
+    cooldowns
+        .get(key)
+        .filter(|until| **until > now)
+        .map(|until| *until - now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FloodAction;
+
+    fn limit(messages: u32) -> FloodLimit {
+        FloodLimit {
+            messages,
+            seconds: 60,
+            action: FloodAction::React,
+        }
+    }
+
+    #[test]
+    fn flood_limit_notifies_once() {
+        let mut cooldowns = Cooldowns::default();
+        let (guild, user) = (GuildId(1), UserId(2));
+
+        assert_eq!(
+            cooldowns.check_flood(guild, user, &limit(2)),
+            Flood::Allowed
+        );
+        assert_eq!(
+            cooldowns.check_flood(guild, user, &limit(2)),
+            Flood::Allowed
+        );
+        assert_eq!(
+            cooldowns.check_flood(guild, user, &limit(2)),
+            Flood::Exceeded
+        );
+        assert_eq!(
+            cooldowns.check_flood(guild, user, &limit(2)),
+            Flood::Limited
+        );
+    }
+
+    #[test]
+    fn flood_limit_is_per_user_and_guild() {
+        let mut cooldowns = Cooldowns::default();
+
+        assert_eq!(
+            cooldowns.check_flood(GuildId(1), UserId(2), &limit(1)),
+            Flood::Allowed
+        );
+        assert_eq!(
+            cooldowns.check_flood(GuildId(1), UserId(3), &limit(1)),
+            Flood::Allowed
+        );
+        assert_eq!(
+            cooldowns.check_flood(GuildId(4), UserId(2), &limit(1)),
+            Flood::Allowed
+        );
+    }
+
+    #[test]
+    fn overlong_cooldown_does_not_panic() {
+        let mut cooldowns = Cooldowns::default();
+        let cooldown = Cooldown {
+            seconds: u64::MAX,
+            scope: CooldownScope::Guild,
+        };
+
+        cooldowns.check_command(GuildId(1), "faq", &cooldown, UserId(2), ChannelId(3));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::config::Config;
+use crate::storage::META_TREE;
+use crate::Data;
+use log::{info, warn};
+use poise::serenity_prelude::{ApplicationFlags, Context, GuildId, Permissions};
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use tokio::task::spawn_blocking;
+
+/// Programs run by commands, with the arguments used to check they work
+const BINARIES: [(&str, &[&str]); 4] = [
+    ("git", &["--version"]),
+    ("gh", &["--version"]),
+    ("packwiz", &["--help"]),
+    ("bash", &["--version"]),
+];
+
+/// Permissions the bot needs in a guild to answer commands, reply in threads and react
+const REQUIRED_PERMISSIONS: Permissions = Permissions::VIEW_CHANNEL
+    .union(Permissions::SEND_MESSAGES)
+    .union(Permissions::EMBED_LINKS)
+    .union(Permissions::ADD_REACTIONS)
+    .union(Permissions::READ_MESSAGE_HISTORY)
+    .union(Permissions::CREATE_PUBLIC_THREADS)
+    .union(Permissions::SEND_MESSAGES_IN_THREADS);
+
+/// Result of a single diagnostic check
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, result: Result<String, String>) -> Self {
+        let (passed, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+
+        Self {
+            name: name.into(),
+            passed,
+            detail,
+        }
+    }
+}
+
+/// Run all checks, looking at the permissions of the bot in `guilds`
+pub async fn run(ctx: &Context, data: &Data, guilds: &[GuildId]) -> Vec<Check> {
+    run_with(ctx, data.config(), data.db.clone(), guilds).await
+}
+
+/// Run all checks without the data of the framework, which isn't there yet during setup
+pub async fn run_with(
+    ctx: &Context,
+    config: Arc<Config>,
+    db: sled::Db,
+    guilds: &[GuildId],
+) -> Vec<Check> {
+    let mut checks = spawn_blocking(move || {
+        let mut checks = binaries();
+        checks.extend(repository(&config));
+        checks.push(database(&db));
+        checks
+    })
+    .await
+    .unwrap_or_else(|e| vec![Check::new("diagnostics", Err(e.to_string()))]);
+
+    checks.push(intents(ctx).await);
+
+    for guild_id in guilds {
+        checks.push(guild_permissions(ctx, *guild_id).await);
+    }
+
+    checks
+}
+
+/// Log the results of checks, failed ones as warnings
+pub fn log(checks: &[Check]) {
+    for check in checks {
+        match check.passed {
+            true => info!("Diagnostics: {} ok, {}", check.name, check.detail),
+            false => warn!("Diagnostics: {} failed, {}", check.name, check.detail),
+        }
+    }
+}
+
+fn binaries() -> Vec<Check> {
+    BINARIES
+        .iter()
+        .map(|(binary, args)| {
+            let result = output(binary, args, None).map(|output| match *binary {
+                // packwiz has no version flag
+                "packwiz" => "installed".to_string(),
+                _ => output.lines().next().unwrap_or_default().to_string(),
+            });
+
+            Check::new(*binary, result)
+        })
+        .collect()
+}
+
+fn repository(config: &Config) -> Vec<Check> {
+    let path = &config.packwiz.repo_path;
+
+    if path.as_os_str().is_empty() {
+        return vec![Check::new(
+            "pack repository",
+            Err("packwiz.repo_path is not set".to_string()),
+        )];
+    }
+
+    if !path.is_dir() {
+        return vec![Check::new(
+            "pack repository",
+            Err(format!("`{}` is not a directory", path.display())),
+        )];
+    }
+
+    let git = |args: &[&str]| output("git", args, Some(path));
+
+    if let Err(e) = git(&["rev-parse", "--is-inside-work-tree"]) {
+        return vec![Check::new(
+            "pack repository",
+            Err(format!(
+                "`{}` is not a git repository: {}",
+                path.display(),
+                e
+            )),
+        )];
+    }
+
+    let branch = git(&["rev-parse", "--abbrev-ref", "HEAD"]).and_then(|branch| {
+        match branch == config.git.branch {
+            true => Ok(branch),
+            false => Err(format!(
+                "on `{}` instead of `{}`",
+                branch, config.git.branch
+            )),
+        }
+    });
+
+    vec![
+        Check::new("pack repository", Ok(path.display().to_string())),
+        Check::new("git branch", branch),
+        Check::new("git remote", git(&["remote", "get-url", "origin"])),
+        Check::new(
+            "gh auth",
+            output("gh", &["auth", "status"], Some(path)).map(|_| "logged in".to_string()),
+        ),
+    ]
+}
+
+/// Check the database can be written to disk.
+/// Writes go to the meta tree, since anything in the default tree counts as legacy data.
+fn database(db: &sled::Db) -> Check {
+    let result = (|| -> sled::Result<()> {
+        let meta = db.open_tree(META_TREE)?;
+        meta.insert("diagnostics", "")?;
+        meta.remove("diagnostics")?;
+        db.flush()?;
+        Ok(())
+    })();
+
+    Check::new(
+        "database",
+        result
+            .map(|_| "writable".to_string())
+            .map_err(|e| e.to_string()),
+    )
+}
+
+/// Check the message content intent is enabled, text commands don't work without it
+async fn intents(ctx: &Context) -> Check {
+    let result = match ctx.http.get_current_application_info().await {
+        Ok(info) => {
+            let flags = info.flags.unwrap_or_else(ApplicationFlags::empty);
+
+            match flags.intersects(
+                ApplicationFlags::GATEWAY_MESSAGE_CONTENT
+                    | ApplicationFlags::GATEWAY_MESSAGE_CONTENT_LIMITED,
+            ) {
+                true => Ok("message content enabled".to_string()),
+                false => Err("the message content intent is not enabled".to_string()),
+            }
+        }
+        Err(e) => Err(format!("couldn't get application info: {}", e)),
+    };
+
+    Check::new("gateway intents", result)
+}
+
+async fn guild_permissions(ctx: &Context, guild_id: GuildId) -> Check {
+    let guild = match guild_id.to_partial_guild(ctx).await {
+        Ok(guild) => guild,
+        Err(e) => return Check::new(format!("permissions in {}", guild_id), Err(e.to_string())),
+    };
+
+    let name = format!("permissions in {}", guild.name);
+
+    let permissions = guild
+        .member_permissions(ctx, ctx.cache.current_user_id())
+        .await
+        .map_err(|e| e.to_string());
+
+    let result = permissions.and_then(|permissions| {
+        let missing = REQUIRED_PERMISSIONS - permissions;
+
+        match missing.is_empty() || permissions.administrator() {
+            true => Ok("all required permissions".to_string()),
+            false => Err(format!(
+                "missing {}",
+                missing.get_permission_names().join(", ")
+            )),
+        }
+    });
+
+    Check::new(name, result)
+}
+
+/// Run a program, returning its trimmed output if it succeeded or what went wrong
+fn output(program: &str, args: &[&str], cwd: Option<&Path>) -> Result<String, String> {
+    let mut command = Command::new(program);
+    command.args(args);
+
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+
+    match command.output() {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        Ok(output) => Err(String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .next()
+            .unwrap_or("exited with an error")
+            .trim()
+            .to_string()),
+        Err(e) => Err(format!("couldn't run {}: {}", program, e)),
+    }
+}
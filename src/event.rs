@@ -1,73 +1,999 @@
-use crate::utils::get_alias;
+use crate::cooldown::Flood;
+use crate::response::{allowed_mentions, Reply, RichResponse};
+use crate::script::{self, Invocation};
+use crate::slash::ARGUMENT_NAME;
+use crate::storage::{
+    Alias, AutoResponder, CustomCommand, FloodAction, ReplyMode, ResponseKind, ScriptData, Store,
+};
+use crate::template::{render, Placeholders};
+use crate::utils::resolve_alias;
 use crate::Data;
 use anyhow::Error;
+use chrono::Utc;
 use log::warn;
-use poise::serenity_prelude::{Context, Message};
+use poise::serenity_prelude::{
+    ApplicationCommandInteraction, Channel, ChannelId, ChannelType, Context, Guild, GuildId,
+    Interaction, InteractionResponseType, Mentionable, Message, MessageId, RoleId, UserId,
+};
 use poise::Event;
-use std::str::from_utf8;
+use std::sync::PoisonError;
+use std::time::Duration;
+use strsim::damerau_levenshtein;
+use tokio::task::spawn_blocking;
+
+/// Reply when a direct message couldn't be sent, usually because the user doesn't allow them
+const DM_FAILED: &str = "I couldn't send you a direct message, check your privacy settings";
+
+/// Fill a message builder with a reply, allowing only the mentions its command opted into
+macro_rules! fill {
+    ($builder:expr, $reply:expr) => {{
+        let builder = $builder;
+        let reply: Reply = $reply;
+
+        builder
+            .content(reply.content)
+            .allowed_mentions(|a| allowed_mentions(&reply.mentions, a));
+
+        if let Some(embed) = reply.embed {
+            builder.set_embed(embed);
+        }
+
+        if let Some(components) = reply.components {
+            builder.set_components(components);
+        }
+
+        builder
+    }};
+}
+
+/// Reaction added to the first command ignored because of the flood limit
+const FLOOD_REACTION: char = '⏳';
+/// Suggestions may differ by one edit for every this many characters of the unknown name
+const MAX_DISTANCE_RATIO: usize = 3;
+/// Unknown names shorter than this don't get suggestions
+const MIN_SUGGESTION_LENGTH: usize = 3;
 
 /// Handle all incoming events  
-/// We're only interested in Messages right now, to implement custom logic
-/// for handling custom commands
+/// We're interested in Messages and slash commands that aren't built-in,
+/// to implement custom logic for handling custom commands
 pub async fn event_handler(
     ctx: &Context,
     event: &Event<'_>,
-    _framework: poise::FrameworkContext<'_, Data, Error>,
+    framework: poise::FrameworkContext<'_, Data, Error>,
     data: &Data,
 ) -> Result<(), Error> {
-    #[allow(clippy::single_match)]
     match event {
-        Event::Message { new_message } => handle_message(new_message, data, ctx).await,
+        Event::Message { new_message } => {
+            handle_message(new_message, &framework.options.commands, data, ctx).await
+        }
+        Event::InteractionCreate {
+            interaction: Interaction::ApplicationCommand(interaction),
+        } => {
+            let builtin = framework
+                .options
+                .commands
+                .iter()
+                .any(|command| command.name == interaction.data.name);
+
+            if !builtin {
+                handle_interaction(interaction, data, ctx).await;
+            }
+        }
         _ => (),
     }
 
     Ok(())
 }
 
-pub async fn handle_message(new_message: &Message, data: &Data, ctx: &Context) {
-    if new_message.guild(&ctx.cache).is_none() {
+pub async fn handle_message(
+    new_message: &Message,
+    builtins: &[poise::Command<Data, Error>],
+    data: &Data,
+    ctx: &Context,
+) {
+    // responses may echo arguments or start with the prefix, so bots could trigger commands
+    if new_message.author.bot {
         return;
     }
 
-    let content = new_message.content.strip_prefix(crate::PREFIX);
+    let guild = match new_message.guild(&ctx.cache) {
+        Some(guild) => guild,
+        None => return,
+    };
 
-    if content.is_none() {
-        return;
-    }
+    let store = match data.guild(guild.id) {
+        Ok(store) => store,
+        Err(e) => {
+            warn!("Error opening guild store: {:?}", e);
+            return;
+        }
+    };
+
+    let prefix = data.prefix(Some(guild.id));
+
+    let content = match strip_prefix(&new_message.content, &prefix, ctx) {
+        Some(content) => content,
+        None => {
+            auto_respond(new_message, &guild, &store, data, ctx).await;
+            return;
+        }
+    };
+
+    // the first word is the command, everything after it is passed as arguments
+    let mut words = content.split_whitespace();
 
-    let mut command = content.unwrap().to_ascii_lowercase();
+    let name = match words.next() {
+        Some(name) => name,
+        None => return,
+    };
 
-    if let Some(alias) = get_alias(&data.tree, command.clone()).await {
-        command = alias;
+    // built-in commands are handled by poise
+    if find_builtin(builtins, name).is_some() {
+        return;
     }
 
-    match data.tree.get(command) {
-        Ok(Some(response)) => {
-            let message = from_utf8(&response).unwrap_or_else(|e| {
-                warn!("Error converting command response to string: {:?}", e);
-                "Error converting command response to string"
-            });
+    let (command, args) = match find_command(&store, name, words.collect()) {
+        Ok(found) => found,
+        Err(e) => {
+            warn!("Error resolving alias: {:?}", e);
+            return;
+        }
+    };
+
+    match store.get::<CustomCommand>(&command) {
+        Ok(Some(custom)) => {
+            let roles = new_message
+                .member
+                .as_ref()
+                .map(|member| member.roles.as_slice())
+                .unwrap_or_default();
 
-            let channel = new_message.channel(&ctx).await;
+            let parent_id = thread_parent(&guild, new_message.channel_id);
 
-            if channel.is_err() {
+            if !permitted(&custom, roles, new_message.channel_id, parent_id) {
                 return;
             }
 
-            let _ = channel
-                .unwrap()
-                .guild()
-                .unwrap()
-                .send_message(&ctx, |m| m.content(message))
+            let throttled = throttle(
+                data,
+                &store,
+                guild.id,
+                &command,
+                &custom,
+                new_message.author.id,
+                new_message.channel_id,
+            );
+
+            match throttled {
+                Ok(()) => (),
+                Err(Throttled::Flooded { notify: true }) => {
+                    let _ = new_message.react(&ctx, FLOOD_REACTION).await;
+                    return;
+                }
+                Err(_) => return,
+            }
+
+            count_use(&store, &command);
+
+            let user = new_message
+                .author_nick(&ctx)
+                .await
+                .unwrap_or_else(|| new_message.author.name.clone());
+
+            let invocation = Invocation {
+                user_id: new_message.author.id,
+                user: user.clone(),
+                roles: roles.to_vec(),
+                args: args.iter().map(|arg| arg.to_string()).collect(),
+                guild_id: guild.id,
+                guild: guild.name.clone(),
+                channel_id: new_message.channel_id,
+            };
+
+            let messages = responses(
+                &store,
+                &command,
+                &custom,
+                Placeholders {
+                    user: &user,
+                    mention: new_message.author.mention().to_string(),
+                    channel: new_message.channel_id.mention().to_string(),
+                    guild: &guild.name,
+                    args,
+                    store: &store,
+                },
+                invocation,
+            )
+            .await;
+
+            deliver(ctx, new_message, &command, custom.reply_mode, messages).await;
+        }
+        Ok(None) => {
+            let entries = sub_entries(&store, &command);
+
+            if !entries.is_empty() {
+                reply_to(
+                    ctx,
+                    new_message,
+                    entries_message(&prefix, &command, &entries),
+                )
+                .await;
+            } else if let Some(suggestion) =
+                suggest(data, &store, builtins, &guild, new_message, name)
+            {
+                reply_to(
+                    ctx,
+                    new_message,
+                    format!("Did you mean `{}{}`?", prefix, suggestion),
+                )
                 .await;
+            }
         }
-        Ok(None) => (),
         Err(e) => {
             warn!("Error getting command from database: {:?}", e);
 
-            let _ = new_message
-                .reply(&ctx, "Error getting command from database")
+            reply_to(ctx, new_message, "Error getting command from database").await;
+        }
+    }
+}
+
+/// Reply to a message without the command prefix with the first auto-responder it triggers
+async fn auto_respond(
+    new_message: &Message,
+    guild: &Guild,
+    store: &Store,
+    data: &Data,
+    ctx: &Context,
+) {
+    // never reply to bots, including this one, to avoid reply loops
+    if new_message.author.bot {
+        return;
+    }
+
+    let channel_id = new_message.channel_id;
+    let parent_id = thread_parent(guild, channel_id);
+
+    let (name, responder) =
+        match find_responder(data, store, &new_message.content, channel_id, parent_id) {
+            Some(found) => found,
+            None => return,
+        };
+
+    if let Some(cooldown) = &responder.cooldown {
+        let left = data
+            .cooldowns
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .check_responder(guild.id, &name, cooldown, new_message.author.id, channel_id);
+
+        if left.is_some() {
+            return;
+        }
+    }
+
+    let user = new_message
+        .author_nick(&ctx)
+        .await
+        .unwrap_or_else(|| new_message.author.name.clone());
+
+    let message = render(
+        &responder.response,
+        &Placeholders {
+            user: &user,
+            mention: new_message.author.mention().to_string(),
+            channel: channel_id.mention().to_string(),
+            guild: &guild.name,
+            args: Vec::new(),
+            store,
+        },
+    );
+
+    let _ = channel_id
+        .send_message(&ctx, |m| fill!(m, Reply::from(message)))
+        .await;
+}
+
+/// Find the first auto-responder triggered by a message that may reply in its channel
+fn find_responder(
+    data: &Data,
+    store: &Store,
+    content: &str,
+    channel_id: ChannelId,
+    parent_id: Option<ChannelId>,
+) -> Option<(String, AutoResponder)> {
+    let mut patterns = data.patterns.lock().unwrap_or_else(PoisonError::into_inner);
+    let channels = [Some(channel_id), parent_id];
+
+    store
+        .scan::<AutoResponder>("")
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!("Error getting auto-responder from database: {:?}", e);
+                None
+            }
+        })
+        .find(|(_, responder)| {
+            responder
+                .channels
+                .as_ref()
+                .is_none_or(|restriction| restriction.permits(channels.iter().flatten()))
+                && patterns.matches(responder, content)
+        })
+}
+
+/// Respond to a custom command registered as a guild slash command
+pub async fn handle_interaction(
+    interaction: &ApplicationCommandInteraction,
+    data: &Data,
+    ctx: &Context,
+) {
+    let guild_id = match interaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+
+    let store = match data.guild(guild_id) {
+        Ok(store) => store,
+        Err(e) => {
+            warn!("Error opening guild store: {:?}", e);
+            return;
+        }
+    };
+
+    let args = interaction
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == ARGUMENT_NAME)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_str())
+        .map(|value| value.split_whitespace().collect())
+        .unwrap_or_default();
+
+    let (messages, mode) = match find_command(&store, &interaction.data.name, args) {
+        Ok((command, args)) => match store.get::<CustomCommand>(&command) {
+            Ok(Some(custom)) => {
+                let roles = interaction
+                    .member
+                    .as_ref()
+                    .map(|member| member.roles.as_slice())
+                    .unwrap_or_default();
+
+                let parent_id = ctx
+                    .cache
+                    .guild(guild_id)
+                    .and_then(|guild| thread_parent(&guild, interaction.channel_id));
+
+                if !permitted(&custom, roles, interaction.channel_id, parent_id) {
+                    respond_ephemeral(ctx, interaction, "You can't use this command here").await;
+
+                    return;
+                }
+
+                let throttled = throttle(
+                    data,
+                    &store,
+                    guild_id,
+                    &command,
+                    &custom,
+                    interaction.user.id,
+                    interaction.channel_id,
+                );
+
+                if let Err(throttled) = throttled {
+                    let message = match throttled {
+                        Throttled::CoolingDown(left) => format!(
+                            "This command is on cooldown, try again in {} seconds",
+                            left.as_secs() + 1
+                        ),
+                        Throttled::Flooded { .. } => {
+                            "You're using commands too quickly, slow down".to_string()
+                        }
+                    };
+
+                    respond_ephemeral(ctx, interaction, message).await;
+
+                    return;
+                }
+
+                count_use(&store, &command);
+
+                let user = interaction
+                    .member
+                    .as_ref()
+                    .and_then(|member| member.nick.clone())
+                    .unwrap_or_else(|| interaction.user.name.clone());
+
+                let guild = guild_id.name(&ctx.cache).unwrap_or_default();
+
+                let invocation = Invocation {
+                    user_id: interaction.user.id,
+                    user: user.clone(),
+                    roles: roles.to_vec(),
+                    args: args.iter().map(|arg| arg.to_string()).collect(),
+                    guild_id,
+                    guild: guild.clone(),
+                    channel_id: interaction.channel_id,
+                };
+
+                let messages = responses(
+                    &store,
+                    &command,
+                    &custom,
+                    Placeholders {
+                        user: &user,
+                        mention: interaction.user.mention().to_string(),
+                        channel: interaction.channel_id.mention().to_string(),
+                        guild: &guild,
+                        args,
+                        store: &store,
+                    },
+                    invocation,
+                )
                 .await;
+
+                (messages, custom.reply_mode)
+            }
+            Ok(None) => match sub_entries(&store, &command) {
+                entries if !entries.is_empty() => (
+                    vec![entries_message(&data.prefix(Some(guild_id)), &command, &entries).into()],
+                    ReplyMode::Send,
+                ),
+                _ => (
+                    vec!["This command doesn't exist anymore".to_string().into()],
+                    ReplyMode::Send,
+                ),
+            },
+            Err(e) => {
+                warn!("Error getting command from database: {:?}", e);
+                (
+                    vec!["Error getting command from database".to_string().into()],
+                    ReplyMode::Send,
+                )
+            }
+        },
+        Err(e) => {
+            warn!("Error resolving alias: {:?}", e);
+            (
+                vec!["Error resolving alias".to_string().into()],
+                ReplyMode::Send,
+            )
+        }
+    };
+
+    deliver_interaction(ctx, interaction, mode, messages).await;
+}
+
+/// Send the replies to a custom command used in a message, according to its reply mode
+async fn deliver(
+    ctx: &Context,
+    new_message: &Message,
+    command: &str,
+    mode: ReplyMode,
+    replies: Vec<Reply>,
+) {
+    let channel_id = match mode {
+        ReplyMode::Send | ReplyMode::Reply => new_message.channel_id,
+        ReplyMode::Thread => {
+            thread_for(ctx, new_message.channel_id, Some(new_message.id), command).await
+        }
+        ReplyMode::Dm => {
+            for reply in replies {
+                let sent = new_message
+                    .author
+                    .direct_message(&ctx, |m| fill!(m, reply))
+                    .await;
+
+                if let Err(e) = sent {
+                    warn!("Error sending direct message: {:?}", e);
+
+                    reply_to(ctx, new_message, DM_FAILED).await;
+                    return;
+                }
+            }
+
+            return;
+        }
+    };
+
+    for reply in replies {
+        let sent = channel_id
+            .send_message(&ctx, |m| {
+                if mode == ReplyMode::Reply {
+                    m.reference_message(new_message);
+                }
+
+                fill!(m, reply)
+            })
+            .await;
+
+        if let Err(e) = sent {
+            warn!("Error sending response of {}: {:?}", command, e);
+
+            reply_to(ctx, new_message, "Error sending response").await;
+            return;
+        }
+    }
+}
+
+/// Send the replies to a custom slash command, according to its reply mode.
+/// Interactions always get a response, ephemeral if the replies went elsewhere.
+async fn deliver_interaction(
+    ctx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    mode: ReplyMode,
+    replies: Vec<Reply>,
+) {
+    match mode {
+        ReplyMode::Send | ReplyMode::Reply => (),
+        ReplyMode::Thread => {
+            let thread =
+                thread_for(ctx, interaction.channel_id, None, &interaction.data.name).await;
+
+            if thread != interaction.channel_id {
+                for reply in replies {
+                    let _ = thread.send_message(&ctx, |m| fill!(m, reply)).await;
+                }
+
+                respond_ephemeral(ctx, interaction, format!("Replied in {}", thread.mention()))
+                    .await;
+                return;
+            }
+        }
+        ReplyMode::Dm => {
+            let mut notice = "Sent you a direct message";
+
+            for reply in replies {
+                let sent = interaction
+                    .user
+                    .direct_message(&ctx, |m| fill!(m, reply))
+                    .await;
+
+                if let Err(e) = sent {
+                    warn!("Error sending direct message: {:?}", e);
+                    notice = DM_FAILED;
+                    break;
+                }
+            }
+
+            respond_ephemeral(ctx, interaction, notice).await;
+            return;
         }
     }
+
+    let mut replies = replies.into_iter();
+
+    // interactions have to be responded to, even if a script didn't send anything
+    // or the response was rejected
+    let responded = match replies.next() {
+        Some(reply) => {
+            interaction
+                .create_interaction_response(&ctx, |r| {
+                    r.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|d| fill!(d, reply))
+                })
+                .await
+        }
+        None => {
+            respond_ephemeral(ctx, interaction, "Done").await;
+            return;
+        }
+    };
+
+    if let Err(e) = responded {
+        warn!(
+            "Error sending response of {}: {:?}",
+            interaction.data.name, e
+        );
+
+        respond_ephemeral(ctx, interaction, "Error sending response").await;
+        return;
+    }
+
+    for reply in replies {
+        let _ = interaction
+            .create_followup_message(&ctx, |m| fill!(m, reply))
+            .await;
+    }
+}
+
+/// Reply to a message without pinging anyone, since the content may contain user input
+async fn reply_to(ctx: &Context, message: &Message, content: impl ToString) {
+    let _ = message
+        .channel_id
+        .send_message(&ctx, |m| {
+            m.reference_message(message);
+            fill!(m, Reply::from(content.to_string()))
+        })
+        .await;
+}
+
+/// Respond to an interaction with a message only the invoker can see
+async fn respond_ephemeral(
+    ctx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    content: impl ToString,
+) {
+    let _ = interaction
+        .create_interaction_response(&ctx, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| d.content(content).ephemeral(true))
+        })
+        .await;
+}
+
+/// Get the channel to post replies in for the thread reply mode:
+/// the current channel if it is a thread or forum post already,
+/// otherwise a new thread, started from the invoking message if there is one.
+/// Falls back to the current channel if no thread can be created.
+async fn thread_for(
+    ctx: &Context,
+    channel_id: ChannelId,
+    message_id: Option<MessageId>,
+    name: &str,
+) -> ChannelId {
+    match channel_id.to_channel(&ctx).await {
+        Ok(Channel::Guild(channel)) if !is_thread(channel.kind) => (),
+        Ok(_) => return channel_id,
+        Err(e) => {
+            warn!("Error getting channel: {:?}", e);
+            return channel_id;
+        }
+    }
+
+    let thread = match message_id {
+        Some(message_id) => {
+            channel_id
+                .create_public_thread(&ctx, message_id, |t| t.name(name))
+                .await
+        }
+        // creating a thread without a starting message is private unless told otherwise
+        None => {
+            channel_id
+                .create_private_thread(&ctx, |t| t.name(name).kind(ChannelType::PublicThread))
+                .await
+        }
+    };
+
+    match thread {
+        Ok(thread) => thread.id,
+        Err(e) => {
+            warn!("Error creating thread: {:?}", e);
+            channel_id
+        }
+    }
+}
+
+/// Check whether a channel is a thread, which includes forum posts
+fn is_thread(kind: ChannelType) -> bool {
+    matches!(
+        kind,
+        ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread
+    )
+}
+
+/// Get the messages a custom command responds with, running its script if it has one
+async fn responses(
+    store: &Store,
+    command: &str,
+    custom: &CustomCommand,
+    placeholders: Placeholders<'_>,
+    invocation: Invocation,
+) -> Vec<Reply> {
+    let mut replies = render_responses(store, command, custom, placeholders, invocation).await;
+
+    for reply in &mut replies {
+        reply.mentions = custom.mentions;
+    }
+
+    replies
+}
+
+async fn render_responses(
+    store: &Store,
+    command: &str,
+    custom: &CustomCommand,
+    placeholders: Placeholders<'_>,
+    invocation: Invocation,
+) -> Vec<Reply> {
+    match custom.kind {
+        ResponseKind::Text => return vec![render(&custom.response, &placeholders).into()],
+        ResponseKind::Embed => {
+            let rendered = RichResponse::parse(&custom.response).and_then(|response| {
+                let response = response.render(|text| render(text, &placeholders));
+                response.validate()?;
+                Ok(response)
+            });
+
+            return match rendered {
+                Ok(response) => vec![response.reply()],
+                Err(e) => {
+                    warn!("Invalid embed response of {}: {:?}", command, e);
+                    vec![format!("Invalid embed response: {}", e).into()]
+                }
+            };
+        }
+        ResponseKind::Script => (),
+    }
+
+    let data = match store.get::<ScriptData>(command) {
+        Ok(data) => data.unwrap_or_default(),
+        Err(e) => {
+            warn!("Error getting script data from database: {:?}", e);
+            return vec!["Error getting script data from database".to_string().into()];
+        }
+    };
+
+    let script = custom.response.clone();
+
+    // scripts may run for a while, so they don't block the event loop
+    match spawn_blocking(move || script::run(&script, invocation, data)).await {
+        Ok(Ok(outcome)) => {
+            if let Err(e) = outcome.save(store, command) {
+                warn!("Error saving script data: {:?}", e);
+            }
+
+            outcome.messages.into_iter().map(Reply::from).collect()
+        }
+        Ok(Err(e)) => vec![format!("Script error: {}", e).into()],
+        Err(e) => {
+            warn!("Error running script: {:?}", e);
+            vec!["Error running script".to_string().into()]
+        }
+    }
+}
+
+/// Find the custom command to run for a name and its arguments, following aliases.
+/// Named entries like `faq install` take precedence over the command itself,
+/// in which case the entry name is removed from the arguments.
+fn find_command<'a>(
+    store: &Store,
+    name: &str,
+    mut args: Vec<&'a str>,
+) -> Result<(String, Vec<&'a str>), Error> {
+    let mut command = resolve_alias(store, &name.to_ascii_lowercase())?;
+
+    if let Some(first) = args.first() {
+        let entry = format!("{} {}", command, first.to_ascii_lowercase());
+
+        if store.contains::<CustomCommand>(&entry)? {
+            command = entry;
+            args.remove(0);
+        }
+    }
+
+    Ok((command, args))
+}
+
+/// Find a built-in prefix command by its name or one of its aliases
+fn find_builtin<'a>(
+    builtins: &'a [poise::Command<Data, Error>],
+    name: &str,
+) -> Option<&'a poise::Command<Data, Error>> {
+    builtins.iter().find(|command| {
+        command.prefix_action.is_some()
+            && (command.name.eq_ignore_ascii_case(name)
+                || command
+                    .aliases
+                    .iter()
+                    .any(|alias| alias.eq_ignore_ascii_case(name)))
+    })
+}
+
+/// Find the built-in command, custom command or alias closest to an unknown command name.
+/// Custom commands the author of `message` can't use there are never suggested.
+/// Returns `None` if nothing is similar enough, suggestions are disabled in the guild
+/// or the user got a suggestion recently.
+fn suggest(
+    data: &Data,
+    store: &Store,
+    builtins: &[poise::Command<Data, Error>],
+    guild: &Guild,
+    message: &Message,
+    name: &str,
+) -> Option<String> {
+    match store.settings() {
+        Ok(settings) if !settings.disable_suggestions => (),
+        Ok(_) => return None,
+        Err(e) => {
+            warn!("Error getting guild settings: {:?}", e);
+            return None;
+        }
+    }
+
+    let name = name.to_lowercase();
+
+    // very short names are usually not meant as commands, like `!!` or `!?`
+    if name.chars().count() < MIN_SUGGESTION_LENGTH {
+        return None;
+    }
+
+    let max_distance = (name.chars().count() / MAX_DISTANCE_RATIO).max(1);
+
+    let builtins = builtins
+        .iter()
+        .filter(|command| command.prefix_action.is_some())
+        .flat_map(|command| {
+            std::iter::once(command.name.clone())
+                .chain(command.aliases.iter().map(|alias| alias.to_string()))
+        });
+
+    let roles = message
+        .member
+        .as_ref()
+        .map(|member| member.roles.as_slice())
+        .unwrap_or_default();
+    let parent_id = thread_parent(guild, message.channel_id);
+    let usable = |custom: &CustomCommand| permitted(custom, roles, message.channel_id, parent_id);
+
+    // named entries are reached through their command, so only the command name is suggested
+    let commands = store
+        .scan::<CustomCommand>("")
+        .filter_map(|entry| entry.ok())
+        .filter(|(_, custom)| usable(custom))
+        .map(|(name, _)| match name.split_once(' ') {
+            Some((name, _)) => name.to_string(),
+            None => name,
+        });
+
+    let aliases = store
+        .scan::<Alias>("")
+        .filter_map(|entry| entry.ok())
+        .filter(|(name, _)| {
+            resolve_alias(store, name)
+                .and_then(|command| store.get::<CustomCommand>(&command))
+                .is_ok_and(|custom| custom.as_ref().is_some_and(usable))
+        })
+        .map(|(name, _)| name);
+
+    let suggestion = builtins
+        .chain(commands)
+        .chain(aliases)
+        .map(|candidate| (damerau_levenshtein(&name, &candidate), candidate))
+        .filter(|(distance, _)| *distance > 0 && *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)?;
+
+    let allowed = data
+        .cooldowns
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .check_suggestion(guild.id, message.author.id);
+
+    allowed.then_some(suggestion)
+}
+
+/// Check the role and channel restrictions of a command.
+/// Messages in a thread count as sent in its parent channel as well.
+fn permitted(
+    custom: &CustomCommand,
+    roles: &[RoleId],
+    channel_id: ChannelId,
+    parent_id: Option<ChannelId>,
+) -> bool {
+    let channels = [Some(channel_id), parent_id];
+
+    custom
+        .roles
+        .as_ref()
+        .is_none_or(|restriction| restriction.permits(roles))
+        && custom
+            .channels
+            .as_ref()
+            .is_none_or(|restriction| restriction.permits(channels.iter().flatten()))
+}
+
+/// Get the parent channel of a thread, `None` for other channels
+fn thread_parent(guild: &Guild, channel_id: ChannelId) -> Option<ChannelId> {
+    guild
+        .threads
+        .iter()
+        .find(|thread| thread.id == channel_id)
+        .and_then(|thread| thread.parent_id)
+}
+
+/// Why a custom command wasn't run
+enum Throttled {
+    /// The command was used recently, with the time until it can be used again
+    CoolingDown(Duration),
+    /// The user exceeded the flood limit of the guild.
+    /// `notify` is set the first time, if the guild wants to react to it.
+    Flooded { notify: bool },
+}
+
+/// Check the cooldown of a command and the flood limit of the guild before running it.
+/// Uses on cooldown don't count towards the flood limit.
+fn throttle(
+    data: &Data,
+    store: &Store,
+    guild_id: GuildId,
+    command: &str,
+    custom: &CustomCommand,
+    user_id: UserId,
+    channel_id: ChannelId,
+) -> Result<(), Throttled> {
+    let mut cooldowns = data
+        .cooldowns
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+
+    let flood_limit = match store.settings() {
+        Ok(settings) => settings.flood_limit,
+        Err(e) => {
+            warn!("Error getting guild settings: {:?}", e);
+            None
+        }
+    };
+
+    if let Some(cooldown) = &custom.cooldown {
+        if let Some(left) =
+            cooldowns.command_cooldown(guild_id, command, cooldown, user_id, channel_id)
+        {
+            return Err(Throttled::CoolingDown(left));
+        }
+    }
+
+    if let Some(limit) = flood_limit {
+        match cooldowns.check_flood(guild_id, user_id, &limit) {
+            Flood::Allowed => (),
+            Flood::Exceeded => {
+                return Err(Throttled::Flooded {
+                    notify: limit.action == FloodAction::React,
+                })
+            }
+            Flood::Limited => return Err(Throttled::Flooded { notify: false }),
+        }
+    }
+
+    // not on cooldown, so this only starts it
+    if let Some(cooldown) = &custom.cooldown {
+        cooldowns.check_command(guild_id, command, cooldown, user_id, channel_id);
+    }
+
+    Ok(())
+}
+
+/// Increase the usage counter of a command
+fn count_use(store: &Store, command: &str) {
+    let used = store.update::<CustomCommand>(command, |custom| {
+        custom.uses += 1;
+        custom.last_used_at = Some(Utc::now());
+    });
+
+    if let Err(e) = used {
+        warn!("Error updating command usage: {:?}", e);
+    }
+}
+
+/// Strip the prefix of the guild or a mention of the bot, like `@bot faq`, from a message
+fn strip_prefix<'a>(content: &'a str, prefix: &str, ctx: &Context) -> Option<&'a str> {
+    if let Some(content) = content.strip_prefix(prefix) {
+        return Some(content);
+    }
+
+    let bot_id = ctx.cache.current_user_id();
+
+    [format!("<@{}>", bot_id), format!("<@!{}>", bot_id)]
+        .iter()
+        .find_map(|mention| content.strip_prefix(mention.as_str()))
+        .map(str::trim_start)
+}
+
+fn entries_message(prefix: &str, command: &str, entries: &[String]) -> String {
+    format!(
+        "Available entries for `{}{}`: {}",
+        prefix,
+        command,
+        entries.join(", ")
+    )
+}
+
+/// Get the names of all sub-entries of a command, e.g. `install` for `faq install`
+fn sub_entries(store: &Store, command: &str) -> Vec<String> {
+    store
+        .scan::<CustomCommand>(&format!("{} ", command))
+        .filter_map(|entry| entry.ok())
+        .filter_map(|(name, _)| name.split_once(' ').map(|(_, entry)| entry.to_string()))
+        .collect()
 }
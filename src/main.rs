@@ -1,22 +1,86 @@
+mod audit;
 mod commands;
+mod config;
+mod confirm;
+mod cooldown;
+mod diagnostics;
 mod event;
+mod permissions;
+mod responder;
+mod response;
+mod script;
+mod slash;
+mod storage;
+mod template;
 mod utils;
 
 extern crate log;
 
+use crate::config::Config;
+use crate::cooldown::Cooldowns;
 use crate::event::event_handler;
+use crate::responder::Patterns;
+use crate::storage::{Store, GLOBAL_TREE};
 use crate::utils::fatal;
 use anyhow::Error;
 use dotenv::dotenv;
-use log::debug;
-use poise::serenity_prelude::{GatewayIntents, UserId};
-use std::collections::HashSet;
+use log::{debug, warn};
+use poise::serenity_prelude::{GatewayIntents, GuildId};
 use std::env;
+use std::sync::{Arc, Mutex, RwLock};
 
-const PREFIX: &str = "!";
+const AUDIT_TREE: &str = "audit";
 
 struct Data {
-    tree: sled::Db,
+    db: sled::Db,
+    cooldowns: Mutex<Cooldowns>,
+    patterns: Mutex<Patterns>,
+    config: RwLock<Arc<Config>>,
+}
+
+impl Data {
+    /// Get the store holding the custom commands, aliases and user data of a guild
+    fn guild(&self, guild_id: GuildId) -> sled::Result<Store> {
+        Ok(Store::new(
+            self.db.open_tree(format!("guild-{}", guild_id.0))?,
+        ))
+    }
+
+    /// Get the current configuration
+    fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Get the prefix of text commands in a guild, the configured one unless the guild set its own
+    fn prefix(&self, guild_id: Option<GuildId>) -> String {
+        if let Some(guild_id) = guild_id {
+            let settings = self
+                .guild(guild_id)
+                .map_err(Error::from)
+                .and_then(|store| store.settings());
+
+            match settings {
+                Ok(settings) => {
+                    if let Some(prefix) = settings.prefix {
+                        return prefix;
+                    }
+                }
+                Err(e) => warn!("Error reading guild prefix: {:?}", e),
+            }
+        }
+
+        self.config().prefix.clone()
+    }
+
+    /// Get the store holding the audit log of all guilds
+    fn audit(&self) -> sled::Result<Store> {
+        Ok(Store::new(self.db.open_tree(AUDIT_TREE)?))
+    }
+
+    /// Get the store holding records shared by all guilds
+    fn global(&self) -> sled::Result<Store> {
+        Ok(Store::new(self.db.open_tree(GLOBAL_TREE)?))
+    }
 }
 
 type Context<'a> = poise::Context<'a, Data, Error>;
@@ -35,15 +99,22 @@ async fn main() {
 
     let token =
         env::var("DISCORD_TOKEN").unwrap_or_else(|e| fatal("DISCORD_TOKEN not found in env!", e));
-    let db_path = env::var("DB_PATH").unwrap_or_else(|e| fatal("DB_PATH not found in env!", e));
+    let config = Config::load().unwrap_or_else(|e| fatal(&format!("{:#}", e), &e));
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
             commands: vec![
+                commands::custom::commands(),
+                commands::custom::alias(),
                 commands::custom::setcommand(),
                 commands::custom::rmalias(),
                 commands::custom::rmcommand(),
                 commands::custom::setalias(),
+                commands::responder::responder(),
+                commands::variable::var(),
+                commands::perm::perm(),
+                commands::audit::audit(),
+                commands::config::config(),
                 commands::dev::register(),
                 commands::fun::yawn(),
                 commands::fun::setyawn(),
@@ -53,36 +124,63 @@ async fn main() {
                 commands::git::reset(),
                 commands::dev::register(),
                 commands::dev::bash(),
+                commands::dev::doctor(),
             ],
             prefix_options: poise::PrefixFrameworkOptions {
-                prefix: Some(PREFIX.into()),
+                dynamic_prefix: Some(|ctx| {
+                    Box::pin(async move { Ok(Some(ctx.data.prefix(ctx.guild_id))) })
+                }),
                 case_insensitive_commands: true,
                 ..Default::default()
             },
             event_handler: |ctx, event, framework, state| {
                 Box::pin(event_handler(ctx, event, framework, state))
             },
-            owners: {
-                let ids = [
-                    // khaoslatet
-                    UserId::from(702874912955695139),
-                    // pixelagent007
-                    UserId::from(487247155741065229),
-                ];
-                HashSet::from(ids)
+            pre_command: |ctx| Box::pin(audit::start(ctx)),
+            post_command: |ctx| Box::pin(audit::finish(ctx)),
+            on_error: |error| {
+                Box::pin(async move {
+                    audit::on_error(&error).await;
+
+                    // custom slash commands are handled in the event handler
+                    if let poise::FrameworkError::UnknownInteraction { .. } = error {
+                        return;
+                    }
+
+                    if let Err(e) = poise::builtins::on_error(error).await {
+                        warn!("Error while handling error: {:?}", e);
+                    }
+                })
             },
             ..Default::default()
         })
         .token(token)
         .intents(GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT)
-        .setup(|_ctx, _ready, _framework| {
+        .setup(move |ctx, ready, _framework| {
             Box::pin(async move {
                 // poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                Ok(Data {
-                    tree: sled::open(db_path).unwrap_or_else(|e| {
-                        fatal("Error opening database, check DB_PATH env variable", e)
-                    }),
-                })
+                let db = sled::open(&config.db_path).unwrap_or_else(|e| {
+                    fatal("Error opening database, check db_path in the config", e)
+                });
+
+                storage::migrate(&db, &config)
+                    .unwrap_or_else(|e| fatal(&format!("Error migrating database: {}", e), &e));
+
+                let data = Data {
+                    db,
+                    cooldowns: Mutex::new(Cooldowns::default()),
+                    patterns: Mutex::new(Patterns::default()),
+                    config: RwLock::new(Arc::new(config)),
+                };
+
+                // checks make requests for every guild, so they don't hold up commands
+                let guilds: Vec<GuildId> = ready.guilds.iter().map(|guild| guild.id).collect();
+                let (ctx, config, db) = (ctx.clone(), data.config(), data.db.clone());
+                tokio::spawn(async move {
+                    diagnostics::log(&diagnostics::run_with(&ctx, config, db, &guilds).await);
+                });
+
+                Ok(data)
             })
         });
 
@@ -0,0 +1,165 @@
+use crate::storage::{Grants, Store};
+use crate::{say, Context};
+use anyhow::Error;
+use log::warn;
+
+/// A named permission, granted to users or roles of a guild.
+/// Global capabilities reach beyond a guild, their grants apply everywhere.
+#[derive(poise::ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    /// Create, change and remove custom commands, aliases, auto-responders and variables
+    #[name = "commands.manage"]
+    CommandsManage,
+    /// Commit, reset and open pull requests in the modpack repository
+    #[name = "git.write"]
+    GitWrite,
+    /// Change the modpack with packwiz
+    #[name = "packwiz.write"]
+    PackwizWrite,
+    /// Run shell commands on the host. Dangerous, so owners need a grant as well.
+    #[name = "shell"]
+    Shell,
+    /// Set a yawn
+    #[name = "yawn.set"]
+    YawnSet,
+    /// Grant and revoke capabilities, and set the audit log channel
+    #[name = "perms.manage"]
+    PermsManage,
+    /// Search the audit log
+    #[name = "audit.view"]
+    AuditView,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 7] = [
+        Capability::CommandsManage,
+        Capability::GitWrite,
+        Capability::PackwizWrite,
+        Capability::Shell,
+        Capability::YawnSet,
+        Capability::PermsManage,
+        Capability::AuditView,
+    ];
+
+    /// Name the grants of this capability are stored under
+    pub fn key(self) -> &'static str {
+        self.name()
+    }
+
+    /// Check whether this capability acts on the host or the modpack rather than a single guild.
+    /// Only owners may grant or revoke these.
+    pub fn is_global(self) -> bool {
+        matches!(
+            self,
+            Capability::GitWrite | Capability::PackwizWrite | Capability::Shell
+        )
+    }
+}
+
+/// Check whether the author of a command is one of the owners in the config
+pub fn is_owner(ctx: Context<'_>) -> bool {
+    ctx.data().config().owners.contains(&ctx.author().id)
+}
+
+/// Get the store holding the grants of a capability,
+/// `None` for capabilities of a guild when not in one
+pub fn grants_store(ctx: Context<'_>, capability: Capability) -> Result<Option<Store>, Error> {
+    if capability.is_global() {
+        return Ok(Some(ctx.data().global()?));
+    }
+
+    Ok(match ctx.guild_id() {
+        Some(guild_id) => Some(ctx.data().guild(guild_id)?),
+        None => None,
+    })
+}
+
+/// Check whether the author of a command has a capability.
+/// Bot owners have every capability except shell, everyone else needs a grant
+/// in the current guild, or a global one for global capabilities.
+pub async fn has_capability(ctx: Context<'_>, capability: Capability) -> Result<bool, Error> {
+    if is_owner(ctx) && capability != Capability::Shell {
+        return Ok(true);
+    }
+
+    let store = match grants_store(ctx, capability)? {
+        Some(store) => store,
+        None => return Ok(false),
+    };
+
+    let grants = store.get::<Grants>(capability.key())?.unwrap_or_default();
+
+    if grants.users.contains(&ctx.author().id) {
+        return Ok(true);
+    }
+
+    if grants.roles.is_empty() {
+        return Ok(false);
+    }
+
+    Ok(match ctx.author_member().await {
+        Some(member) => member.roles.iter().any(|role| grants.roles.contains(role)),
+        None => false,
+    })
+}
+
+/// Check a capability and tell the author if they lack it
+async fn require(ctx: Context<'_>, capability: Capability) -> Result<bool, Error> {
+    let allowed = match has_capability(ctx, capability).await {
+        Ok(allowed) => allowed,
+        Err(e) => {
+            warn!("Error checking permissions: {:?}", e);
+            false
+        }
+    };
+
+    if !allowed {
+        say!(
+            ctx,
+            "You need the `{}` permission to use this command",
+            capability.name()
+        );
+    }
+
+    Ok(allowed)
+}
+
+// command checks, used like `#[poise::command(check = "commands_manage")]`
+
+pub async fn owner(ctx: Context<'_>) -> Result<bool, Error> {
+    let allowed = is_owner(ctx);
+
+    if !allowed {
+        say!(ctx, "Only owners of the bot can use this command");
+    }
+
+    Ok(allowed)
+}
+
+pub async fn commands_manage(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::CommandsManage).await
+}
+
+pub async fn git_write(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::GitWrite).await
+}
+
+pub async fn packwiz_write(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::PackwizWrite).await
+}
+
+pub async fn shell(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::Shell).await
+}
+
+pub async fn yawn_set(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::YawnSet).await
+}
+
+pub async fn perms_manage(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::PermsManage).await
+}
+
+pub async fn audit_view(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::AuditView).await
+}
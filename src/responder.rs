@@ -0,0 +1,55 @@
+use crate::storage::{AutoResponder, TriggerKind};
+use log::warn;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+
+/// Compiled regexes may be at most this large, since every message is matched against them
+const REGEX_SIZE_LIMIT: usize = 1 << 18;
+/// The cache is cleared once it holds this many regexes
+const CACHE_LIMIT: usize = 256;
+
+/// Compile the pattern of a regex auto-responder
+pub fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+/// Compiled regexes of auto-responders, keyed by their pattern
+#[derive(Default)]
+pub struct Patterns {
+    regexes: HashMap<String, Regex>,
+}
+
+impl Patterns {
+    /// Check whether a message triggers an auto-responder
+    pub fn matches(&mut self, responder: &AutoResponder, content: &str) -> bool {
+        match responder.kind {
+            TriggerKind::Keyword => content
+                .to_lowercase()
+                .contains(&responder.pattern.to_lowercase()),
+            TriggerKind::Regex => {
+                if !self.regexes.contains_key(&responder.pattern) {
+                    let regex = match compile(&responder.pattern) {
+                        Ok(regex) => regex,
+                        Err(e) => {
+                            warn!(
+                                "Invalid auto-responder regex {:?}: {}",
+                                responder.pattern, e
+                            );
+                            return false;
+                        }
+                    };
+
+                    if self.regexes.len() >= CACHE_LIMIT {
+                        self.regexes.clear();
+                    }
+
+                    self.regexes.insert(responder.pattern.clone(), regex);
+                }
+
+                self.regexes[&responder.pattern].is_match(content)
+            }
+        }
+    }
+}
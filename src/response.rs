@@ -0,0 +1,330 @@
+use crate::storage::Mentions;
+use anyhow::{bail, Error};
+use poise::serenity_prelude::{
+    ButtonStyle, CreateAllowedMentions, CreateComponents, CreateEmbed, ParseValue,
+};
+use serde::{Deserialize, Serialize};
+
+// limits enforced by Discord
+const MAX_TITLE_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+const MAX_FIELDS: usize = 25;
+const MAX_FIELD_NAME_LENGTH: usize = 256;
+const MAX_FIELD_VALUE_LENGTH: usize = 1024;
+const MAX_FOOTER_LENGTH: usize = 2048;
+/// Limit on all text of an embed together
+const MAX_EMBED_LENGTH: usize = 6000;
+const MAX_CONTENT_LENGTH: usize = 2000;
+const MAX_BUTTON_LABEL_LENGTH: usize = 80;
+const BUTTONS_PER_ROW: usize = 5;
+const MAX_ROWS: usize = 5;
+
+/// A message sent in response to a custom command
+#[derive(Default)]
+pub struct Reply {
+    pub content: String,
+    pub embed: Option<CreateEmbed>,
+    pub components: Option<CreateComponents>,
+    /// Mentions that may ping, none by default
+    pub mentions: Mentions,
+}
+
+/// Restrict the mentions of a message to the kinds that are allowed to ping
+pub fn allowed_mentions<'a>(
+    mentions: &Mentions,
+    allowed: &'a mut CreateAllowedMentions,
+) -> &'a mut CreateAllowedMentions {
+    allowed.empty_parse();
+
+    for (enabled, value) in [
+        (mentions.users, ParseValue::Users),
+        (mentions.roles, ParseValue::Roles),
+        (mentions.everyone, ParseValue::Everyone),
+    ] {
+        if enabled {
+            allowed.parse(value);
+        }
+    }
+
+    allowed.replied_user(mentions.users)
+}
+
+impl From<String> for Reply {
+    fn from(content: String) -> Self {
+        Self {
+            content,
+            ..Default::default()
+        }
+    }
+}
+
+/// Response of an embed command, stored as JSON like
+/// `{"embed": {"title": "Install", "fields": [{"name": "Step 1", "value": "..."}]}, "buttons": [{"label": "Wiki", "url": "https://..."}]}`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RichResponse {
+    /// Text sent along with the embed
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub embed: Option<EmbedPayload>,
+    #[serde(default)]
+    pub buttons: Vec<LinkButton>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct EmbedPayload {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Link opened by clicking the title
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Hex colour like `#5865F2`
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    #[serde(default)]
+    pub footer: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<EmbedField>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub inline: bool,
+}
+
+/// A button opening a link
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LinkButton {
+    pub label: String,
+    pub url: String,
+}
+
+impl RichResponse {
+    /// Parse a response from JSON and check it against the limits of Discord
+    pub fn parse(json: &str) -> Result<Self, Error> {
+        let response: Self = serde_json::from_str(json)?;
+        response.validate()?;
+
+        Ok(response)
+    }
+
+    /// Check the response against the limits of Discord,
+    /// which rendered responses may exceed after placeholders are filled in
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.content.is_none() && self.embed.is_none() {
+            bail!("the response needs content or an embed");
+        }
+
+        check_length("content", &self.content, MAX_CONTENT_LENGTH)?;
+
+        if let Some(embed) = &self.embed {
+            check_length("title", &embed.title, MAX_TITLE_LENGTH)?;
+            check_length("description", &embed.description, MAX_DESCRIPTION_LENGTH)?;
+            check_length("footer", &embed.footer, MAX_FOOTER_LENGTH)?;
+
+            for url in [&embed.url, &embed.image, &embed.thumbnail]
+                .into_iter()
+                .flatten()
+            {
+                check_url(url)?;
+            }
+
+            if let Some(color) = &embed.color {
+                parse_color(color)?;
+            }
+
+            if embed.fields.len() > MAX_FIELDS {
+                bail!("embeds can have at most {} fields", MAX_FIELDS);
+            }
+
+            for field in &embed.fields {
+                if field.name.trim().is_empty() || field.value.trim().is_empty() {
+                    bail!("field names and values can't be empty");
+                }
+
+                check_length("field name", &Some(&field.name), MAX_FIELD_NAME_LENGTH)?;
+                check_length("field value", &Some(&field.value), MAX_FIELD_VALUE_LENGTH)?;
+            }
+
+            let length: usize = [&embed.title, &embed.description, &embed.footer]
+                .into_iter()
+                .flatten()
+                .chain(
+                    embed
+                        .fields
+                        .iter()
+                        .flat_map(|field| [&field.name, &field.value]),
+                )
+                .map(|text| text.chars().count())
+                .sum();
+
+            if length > MAX_EMBED_LENGTH {
+                bail!(
+                    "all text of an embed can be at most {} characters together",
+                    MAX_EMBED_LENGTH
+                );
+            }
+        }
+
+        if self.buttons.len() > BUTTONS_PER_ROW * MAX_ROWS {
+            bail!("at most {} buttons are allowed", BUTTONS_PER_ROW * MAX_ROWS);
+        }
+
+        for button in &self.buttons {
+            check_length(
+                "button label",
+                &Some(&button.label),
+                MAX_BUTTON_LABEL_LENGTH,
+            )?;
+            check_url(&button.url)?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply `render` to all text of the response, like filling in placeholders
+    pub fn render(&self, render: impl Fn(&str) -> String) -> Self {
+        let render_option = |text: &Option<String>| text.as_deref().map(&render);
+
+        Self {
+            content: render_option(&self.content),
+            embed: self.embed.as_ref().map(|embed| EmbedPayload {
+                title: render_option(&embed.title),
+                description: render_option(&embed.description),
+                footer: render_option(&embed.footer),
+                fields: embed
+                    .fields
+                    .iter()
+                    .map(|field| EmbedField {
+                        name: render(&field.name),
+                        value: render(&field.value),
+                        inline: field.inline,
+                    })
+                    .collect(),
+                ..embed.clone()
+            }),
+            buttons: self
+                .buttons
+                .iter()
+                .map(|button| LinkButton {
+                    label: render(&button.label),
+                    url: button.url.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Build the message for this response
+    pub fn reply(&self) -> Reply {
+        Reply {
+            content: self.content.clone().unwrap_or_default(),
+            embed: self.embed.as_ref().map(EmbedPayload::build),
+            components: match self.buttons.is_empty() {
+                true => None,
+                false => Some(self.build_buttons()),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn build_buttons(&self) -> CreateComponents {
+        let mut components = CreateComponents::default();
+
+        for row in self.buttons.chunks(BUTTONS_PER_ROW) {
+            components.create_action_row(|r| {
+                for button in row {
+                    r.create_button(|b| {
+                        b.style(ButtonStyle::Link)
+                            .label(&button.label)
+                            .url(&button.url)
+                    });
+                }
+
+                r
+            });
+        }
+
+        components
+    }
+}
+
+impl EmbedPayload {
+    fn build(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+
+        if let Some(title) = &self.title {
+            embed.title(title);
+        }
+
+        if let Some(description) = &self.description {
+            embed.description(description);
+        }
+
+        if let Some(url) = &self.url {
+            embed.url(url);
+        }
+
+        if let Some(color) = self
+            .color
+            .as_deref()
+            .and_then(|color| parse_color(color).ok())
+        {
+            embed.color(color);
+        }
+
+        if let Some(image) = &self.image {
+            embed.image(image);
+        }
+
+        if let Some(thumbnail) = &self.thumbnail {
+            embed.thumbnail(thumbnail);
+        }
+
+        if let Some(footer) = &self.footer {
+            embed.footer(|f| f.text(footer));
+        }
+
+        for field in &self.fields {
+            embed.field(&field.name, &field.value, field.inline);
+        }
+
+        embed
+    }
+}
+
+fn check_length(name: &str, text: &Option<impl AsRef<str>>, max: usize) -> Result<(), Error> {
+    match text {
+        Some(text) if text.as_ref().chars().count() > max => {
+            bail!("the {} can be at most {} characters long", name, max)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_url(url: &str) -> Result<(), Error> {
+    match url.starts_with("https://") || url.starts_with("http://") {
+        true => Ok(()),
+        false => bail!("`{}` is not a http(s) link", url),
+    }
+}
+
+/// Parse a hex colour like `#5865F2`
+fn parse_color(color: &str) -> Result<u32, Error> {
+    match u32::from_str_radix(color.trim_start_matches('#'), 16) {
+        Ok(color) if color <= 0xFFFFFF => Ok(color),
+        _ => bail!("`{}` is not a hex colour like #5865F2", color),
+    }
+}
@@ -0,0 +1,248 @@
+use crate::storage::{ScriptData, Store};
+use anyhow::{anyhow, Error};
+use poise::serenity_prelude::{ChannelId, GuildId, RoleId, UserId};
+use rand::Rng;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, INT};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Scripts are stopped once they ran for this long
+const TIME_LIMIT: Duration = Duration::from_millis(500);
+/// The time limit is checked every this many operations
+const TIME_CHECK_INTERVAL: u64 = 1024;
+const MAX_OPERATIONS: u64 = 1_000_000;
+/// Strings are limited to the length of a Discord message
+const MAX_STRING_SIZE: usize = 2000;
+const MAX_COLLECTION_SIZE: usize = 1024;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_MESSAGES: usize = 5;
+/// Number of keys a single command may keep in its key-value store
+const MAX_VALUES: usize = 100;
+
+/// Information about the use of a scripted command, available to the script
+pub struct Invocation {
+    pub user_id: UserId,
+    /// Nickname or username of the invoker
+    pub user: String,
+    pub roles: Vec<RoleId>,
+    pub args: Vec<String>,
+    pub guild_id: GuildId,
+    pub guild: String,
+    pub channel_id: ChannelId,
+}
+
+/// Result of running a script
+#[derive(Default)]
+pub struct Outcome {
+    /// Messages to send, in order
+    pub messages: Vec<String>,
+    /// Keys the script set or removed in its key-value store
+    pub changes: BTreeMap<String, Option<Value>>,
+}
+
+impl Outcome {
+    /// Write the changes of the script to its key-value store
+    pub fn save(&self, store: &Store, command: &str) -> Result<(), Error> {
+        if self.changes.is_empty() {
+            return Ok(());
+        }
+
+        store.upsert::<ScriptData>(command, |data| {
+            for (key, value) in &self.changes {
+                match value {
+                    Some(value) => data.values.insert(key.clone(), value.clone()),
+                    None => data.values.remove(key),
+                };
+            }
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Check whether a script compiles, without running it
+pub fn check(script: &str) -> Result<(), Error> {
+    engine().compile(script)?;
+    Ok(())
+}
+
+/// Run the script of a command.
+/// Besides the standard library, scripts can use:
+/// - `invoker`: map with the `id`, `name` and `roles` of the user
+/// - `args`: array of the arguments
+/// - `guild`: map with the `id` and `name` of the guild
+/// - `channel`: id of the channel
+/// - `send(message)`: send a message
+/// - `get_value(key)` and `set_value(key, value)`: the key-value store of the command,
+///   setting a key to `()` removes it
+/// - `random(n)` and `random(min, max)`: random integers, excluding `n` and `max`
+///
+/// The value the script evaluates to is sent as a message too, unless it's `()`.
+/// Ids are strings.
+pub fn run(script: &str, invocation: Invocation, data: ScriptData) -> Result<Outcome, Error> {
+    let mut engine = engine();
+
+    let start = Instant::now();
+
+    engine.on_progress(move |operations| {
+        match operations % TIME_CHECK_INTERVAL == 0 && start.elapsed() > TIME_LIMIT {
+            true => Some("time limit exceeded".into()),
+            false => None,
+        }
+    });
+
+    let outcome = Rc::new(RefCell::new(Outcome::default()));
+    let values = Rc::new(RefCell::new(data.values));
+
+    let sent = outcome.clone();
+    engine.register_fn(
+        "send",
+        move |message: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let messages = &mut sent.borrow_mut().messages;
+
+            if messages.len() >= MAX_MESSAGES {
+                return Err(format!("scripts may send at most {} messages", MAX_MESSAGES).into());
+            }
+
+            messages.push(message.to_string());
+            Ok(())
+        },
+    );
+
+    let read = values.clone();
+    engine.register_fn("get_value", move |key: &str| -> Dynamic {
+        read.borrow().get(key).map_or(Dynamic::UNIT, to_dynamic)
+    });
+
+    let written = outcome.clone();
+    let write = values.clone();
+    engine.register_fn(
+        "set_value",
+        move |key: &str, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let mut values = write.borrow_mut();
+            let value = from_dynamic(value)?;
+
+            match &value {
+                Some(value) => {
+                    if values.len() >= MAX_VALUES && !values.contains_key(key) {
+                        return Err(
+                            format!("scripts may store at most {} values", MAX_VALUES).into()
+                        );
+                    }
+
+                    values.insert(key.to_string(), value.clone());
+                }
+                None => {
+                    values.remove(key);
+                }
+            }
+
+            written.borrow_mut().changes.insert(key.to_string(), value);
+            Ok(())
+        },
+    );
+
+    engine.register_fn("random", |n: INT| -> Result<INT, Box<EvalAltResult>> {
+        random(0, n)
+    });
+    engine.register_fn("random", random);
+
+    let mut invoker = Map::new();
+    invoker.insert("id".into(), invocation.user_id.to_string().into());
+    invoker.insert("name".into(), invocation.user.into());
+    invoker.insert(
+        "roles".into(),
+        Dynamic::from_array(
+            invocation
+                .roles
+                .iter()
+                .map(|role| role.to_string().into())
+                .collect(),
+        ),
+    );
+
+    let mut guild = Map::new();
+    guild.insert("id".into(), invocation.guild_id.to_string().into());
+    guild.insert("name".into(), invocation.guild.into());
+
+    let args: Array = invocation.args.into_iter().map(Dynamic::from).collect();
+
+    let mut scope = Scope::new();
+    scope
+        .push_constant("invoker", invoker)
+        .push_constant("guild", guild)
+        .push_constant("args", args)
+        .push_constant("channel", invocation.channel_id.to_string());
+
+    let result = engine
+        .eval_with_scope::<Dynamic>(&mut scope, script)
+        .map_err(|e| anyhow!("{}", e))?;
+
+    let mut outcome = outcome.take();
+
+    if !result.is_unit() {
+        outcome.messages.push(result.to_string());
+    }
+
+    Ok(outcome)
+}
+
+/// Create an engine without access to the file system or other modules,
+/// with limits on the resources a script may use
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .on_print(|_| ())
+        .on_debug(|_, _, _| ());
+
+    engine.disable_symbol("eval");
+
+    engine
+}
+
+fn random(min: INT, max: INT) -> Result<INT, Box<EvalAltResult>> {
+    match min < max {
+        true => Ok(rand::thread_rng().gen_range(min..max)),
+        false => Err(format!("random: {} is not less than {}", min, max).into()),
+    }
+}
+
+fn to_dynamic(value: &Value) -> Dynamic {
+    match value {
+        Value::Bool(value) => (*value).into(),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => value.into(),
+            None => number.as_f64().unwrap_or_default().into(),
+        },
+        Value::String(value) => value.clone().into(),
+        _ => Dynamic::UNIT,
+    }
+}
+
+/// Convert a value set by a script for storing it, `None` if the key should be removed
+fn from_dynamic(value: Dynamic) -> Result<Option<Value>, Box<EvalAltResult>> {
+    if value.is_unit() {
+        Ok(None)
+    } else if let Ok(value) = value.as_bool() {
+        Ok(Some(value.into()))
+    } else if let Ok(value) = value.as_int() {
+        Ok(Some(value.into()))
+    } else if let Ok(value) = value.as_float() {
+        Ok(Some(value.into()))
+    } else if value.is_string() {
+        Ok(Some(value.into_string()?.into()))
+    } else {
+        Err("only strings, numbers and booleans can be stored".into())
+    }
+}
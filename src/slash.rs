@@ -0,0 +1,124 @@
+use crate::storage::{CustomCommand, ResponseKind, Store};
+use crate::utils::preview;
+use crate::Data;
+use anyhow::Error;
+use poise::serenity_prelude::{CommandOptionType, CreateApplicationCommands, GuildId, Http};
+use std::collections::BTreeMap;
+
+/// Name of the optional argument of custom slash commands
+pub const ARGUMENT_NAME: &str = "arguments";
+
+/// Discord allows at most this many slash commands per guild
+const MAX_GUILD_COMMANDS: usize = 100;
+const MAX_DESCRIPTION_LENGTH: usize = 99;
+
+/// Register the custom commands of a guild as guild slash commands, if enabled in its settings.
+/// Built-in commands that were registered in the guild are kept.
+/// Returns the number of custom commands that were registered.
+pub async fn sync(
+    http: &Http,
+    builtins: &[poise::Command<Data, Error>],
+    guild_id: GuildId,
+    store: &Store,
+) -> Result<usize, Error> {
+    let existing = guild_id.get_application_commands(http).await?;
+
+    let builtins_registered = existing
+        .iter()
+        .any(|command| builtins.iter().any(|builtin| builtin.name == command.name));
+
+    let (mut builder, limit) = match builtins_registered {
+        true => (
+            poise::builtins::create_application_commands(builtins),
+            MAX_GUILD_COMMANDS.saturating_sub(builtins.len()),
+        ),
+        false => (CreateApplicationCommands::default(), MAX_GUILD_COMMANDS),
+    };
+
+    let custom = match store.settings()?.slash_commands {
+        true => custom_commands(store)?,
+        false => BTreeMap::new(),
+    };
+
+    if existing.is_empty() && custom.is_empty() {
+        return Ok(0);
+    }
+
+    let custom: Vec<_> = custom
+        .into_iter()
+        .filter(|(name, _)| !builtins.iter().any(|builtin| builtin.name == *name))
+        .take(limit)
+        .collect();
+
+    for (name, description) in &custom {
+        builder.create_application_command(|c| {
+            c.name(name).description(description).create_option(|o| {
+                o.name(ARGUMENT_NAME)
+                    .description("Arguments or entry name passed to the command")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+            })
+        });
+    }
+
+    guild_id
+        .set_application_commands(http, |commands| {
+            *commands = builder;
+            commands
+        })
+        .await?;
+
+    Ok(custom.len())
+}
+
+/// Get the names and descriptions of all custom commands usable as slash commands.
+/// Named entries are reached through the argument of their command.
+fn custom_commands(store: &Store) -> Result<BTreeMap<String, String>, Error> {
+    let mut commands = BTreeMap::new();
+
+    for entry in store.scan::<CustomCommand>("") {
+        let (name, command) = entry?;
+
+        match name.split_once(' ') {
+            Some((name, entry)) => {
+                let description = commands
+                    .entry(name.to_string())
+                    .or_insert_with(|| "Entries:".to_string());
+
+                if description.starts_with("Entries:") {
+                    description.push_str(&format!(" {}", entry));
+                }
+            }
+            None => {
+                // scripts and embed payloads make for bad descriptions
+                let description = command.description.unwrap_or_else(|| match command.kind {
+                    ResponseKind::Text => command.response.clone(),
+                    _ => String::new(),
+                });
+
+                commands.insert(name, description);
+            }
+        }
+    }
+
+    Ok(commands
+        .into_iter()
+        .filter(|(name, _)| valid_name(name))
+        .map(|(name, description)| {
+            let description = match description.trim().is_empty() {
+                true => "Custom command".to_string(),
+                false => preview(&description, MAX_DESCRIPTION_LENGTH),
+            };
+
+            (name, description)
+        })
+        .collect())
+}
+
+/// Check whether a name is allowed as a slash command name by Discord, which rejects uppercase
+fn valid_name(name: &str) -> bool {
+    (1..=32).contains(&name.chars().count())
+        && name
+            .chars()
+            .all(|c| c.is_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
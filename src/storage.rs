@@ -0,0 +1,735 @@
+use crate::config::Config;
+use crate::permissions::Capability;
+use anyhow::{bail, Error};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use poise::serenity_prelude::{ChannelId, GuildId, RoleId, UserId};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::from_utf8;
+
+/// Version of the database layout, bumped whenever a migration is added
+pub const SCHEMA_VERSION: u32 = 2;
+
+pub const META_TREE: &str = "meta";
+/// Tree of records shared by all guilds, like grants of global capabilities
+pub const GLOBAL_TREE: &str = "global";
+const SCHEMA_VERSION_KEY: &str = "schema-version";
+const SETTINGS_NAME: &str = "guild";
+
+/// A typed value stored in a guild tree
+pub trait Record: Serialize + DeserializeOwned {
+    /// Key prefix separating this kind of record from others
+    const PREFIX: &'static str;
+    /// Version of the serialized format of this record
+    const VERSION: u32;
+}
+
+/// A record as it is written to the database, tagged with its version
+#[derive(Serialize, Deserialize)]
+struct Versioned<R> {
+    version: u32,
+    record: R,
+}
+
+/// A custom command, or a named entry of one like `faq install`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CustomCommand {
+    /// Response template, the source of the script for scripted commands
+    /// or the JSON payload for embed commands
+    pub response: String,
+    #[serde(default)]
+    pub kind: ResponseKind,
+    /// Description shown in the slash command picker
+    #[serde(default)]
+    pub description: Option<String>,
+    /// User who created the command, unknown for commands created before this was tracked
+    #[serde(default)]
+    pub author: Option<UserId>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// User who last changed the response
+    #[serde(default)]
+    pub updated_by: Option<UserId>,
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    /// How often the command has been used
+    #[serde(default)]
+    pub uses: u64,
+    #[serde(default)]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub cooldown: Option<Cooldown>,
+    /// Roles that may or may not use the command
+    #[serde(default)]
+    pub roles: Option<Restriction<RoleId>>,
+    /// Channels the command may or may not be used in
+    #[serde(default)]
+    pub channels: Option<Restriction<ChannelId>>,
+    /// Mentions the response may ping, none unless opted into
+    #[serde(default)]
+    pub mentions: Mentions,
+    #[serde(default)]
+    pub reply_mode: ReplyMode,
+}
+
+/// Kinds of mentions a response may ping
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Mentions {
+    /// Users, including the invoker when replying to them
+    #[serde(default)]
+    pub users: bool,
+    #[serde(default)]
+    pub roles: bool,
+    /// `@everyone` and `@here`
+    #[serde(default)]
+    pub everyone: bool,
+}
+
+/// Where the response of a custom command is sent
+#[derive(
+    Serialize, Deserialize, poise::ChoiceParameter, Clone, Copy, Debug, Default, PartialEq, Eq,
+)]
+pub enum ReplyMode {
+    /// Send it in the channel the command was used in
+    #[default]
+    #[name = "send"]
+    Send,
+    /// Reply to the message of the invoker
+    #[name = "reply"]
+    Reply,
+    /// Send it to the invoker in a direct message
+    #[name = "dm"]
+    Dm,
+    /// Send it in a new thread, or the current one if the command was used in a thread or forum post
+    #[name = "thread"]
+    Thread,
+}
+
+/// An allowlist or denylist of roles or channels
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Restriction<T> {
+    pub mode: RestrictionMode,
+    pub ids: Vec<T>,
+}
+
+impl<T: PartialEq> Restriction<T> {
+    /// Check whether something with the given ids, like a member with their roles, is permitted
+    pub fn permits<'a>(&self, ids: impl IntoIterator<Item = &'a T>) -> bool
+    where
+        T: 'a,
+    {
+        let listed = ids.into_iter().any(|id| self.ids.contains(id));
+
+        match self.mode {
+            RestrictionMode::Allow => listed,
+            RestrictionMode::Deny => !listed,
+        }
+    }
+}
+
+/// Whether a restriction lists what is allowed or what is denied
+#[derive(Serialize, Deserialize, poise::ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestrictionMode {
+    /// Only the listed roles or channels are allowed
+    #[name = "allow"]
+    Allow,
+    /// The listed roles or channels are denied
+    #[name = "deny"]
+    Deny,
+}
+
+/// How the response of a custom command is produced
+#[derive(
+    Serialize, Deserialize, poise::ChoiceParameter, Clone, Copy, Debug, Default, PartialEq, Eq,
+)]
+pub enum ResponseKind {
+    /// The response is a template filled with placeholders
+    #[default]
+    #[name = "text"]
+    Text,
+    /// The response is a script run in a sandbox
+    #[name = "script"]
+    Script,
+    /// The response is a JSON payload describing an embed and link buttons
+    #[name = "embed"]
+    Embed,
+}
+
+/// Values a scripted command keeps between uses
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ScriptData {
+    pub values: BTreeMap<String, serde_json::Value>,
+}
+
+impl Record for ScriptData {
+    const PREFIX: &'static str = "script";
+    const VERSION: u32 = 1;
+}
+
+/// How long a command can't be used again after it was used
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Cooldown {
+    pub seconds: u64,
+    pub scope: CooldownScope,
+}
+
+impl Cooldown {
+    /// Longest cooldown that can be set, a week
+    pub const MAX_SECONDS: u64 = 7 * 24 * 60 * 60;
+}
+
+/// Who a cooldown applies to
+#[derive(Serialize, Deserialize, poise::ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CooldownScope {
+    /// Each user has their own cooldown
+    #[name = "user"]
+    User,
+    /// Each channel has its own cooldown
+    #[name = "channel"]
+    Channel,
+    /// The cooldown applies to everyone in the guild
+    #[name = "guild"]
+    Guild,
+}
+
+impl CustomCommand {
+    pub fn new(response: String, author: UserId) -> Self {
+        let now = Utc::now();
+
+        Self {
+            response,
+            author: Some(author),
+            created_at: Some(now),
+            updated_by: Some(author),
+            updated_at: Some(now),
+            ..Default::default()
+        }
+    }
+
+    /// Check whether the settings of two commands match, ignoring authorship and usage
+    pub fn same_settings(&self, other: &CustomCommand) -> bool {
+        self.response == other.response
+            && self.kind == other.kind
+            && self.description == other.description
+            && self.cooldown == other.cooldown
+            && self.roles == other.roles
+            && self.channels == other.channels
+            && self.mentions == other.mentions
+            && self.reply_mode == other.reply_mode
+    }
+
+    /// Take the settings of `other`, keeping the authorship and usage of this command
+    pub fn with_settings(self, other: CustomCommand) -> Self {
+        Self {
+            response: other.response,
+            kind: other.kind,
+            description: other.description,
+            cooldown: other.cooldown,
+            roles: other.roles,
+            channels: other.channels,
+            mentions: other.mentions,
+            reply_mode: other.reply_mode,
+            ..self
+        }
+    }
+}
+
+impl Record for CustomCommand {
+    const PREFIX: &'static str = "command";
+    const VERSION: u32 = 1;
+}
+
+/// Replies to messages containing a keyword or matching a regex, without a command prefix
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AutoResponder {
+    pub kind: TriggerKind,
+    pub pattern: String,
+    pub response: String,
+    #[serde(default)]
+    pub author: Option<UserId>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub cooldown: Option<Cooldown>,
+    /// Channels the responder may or may not reply in
+    #[serde(default)]
+    pub channels: Option<Restriction<ChannelId>>,
+}
+
+impl Record for AutoResponder {
+    const PREFIX: &'static str = "responder";
+    const VERSION: u32 = 1;
+}
+
+/// How the pattern of an auto-responder is matched against messages
+#[derive(Serialize, Deserialize, poise::ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerKind {
+    /// The message contains the pattern, ignoring case
+    #[name = "keyword"]
+    Keyword,
+    /// The message matches the pattern as a regex
+    #[name = "regex"]
+    Regex,
+}
+
+/// An alternative name for a custom command
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Alias {
+    pub command: String,
+}
+
+impl Record for Alias {
+    const PREFIX: &'static str = "alias";
+    const VERSION: u32 = 1;
+}
+
+/// A named value shared by custom command responses, also used as a counter
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Variable {
+    pub value: String,
+}
+
+impl Record for Variable {
+    const PREFIX: &'static str = "var";
+    const VERSION: u32 = 1;
+}
+
+/// Users and roles granted a capability, stored under the name of the capability
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Grants {
+    #[serde(default)]
+    pub users: Vec<UserId>,
+    #[serde(default)]
+    pub roles: Vec<RoleId>,
+}
+
+impl Record for Grants {
+    const PREFIX: &'static str = "perm";
+    const VERSION: u32 = 1;
+}
+
+/// Data stored for a single user
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UserProfile {
+    pub yawn: Option<String>,
+}
+
+impl Record for UserProfile {
+    const PREFIX: &'static str = "user";
+    const VERSION: u32 = 1;
+}
+
+/// Settings of a guild, stored as a single record
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GuildSettings {
+    /// Register custom commands as guild slash commands
+    #[serde(default)]
+    pub slash_commands: bool,
+    /// How many custom commands a user may use in a time frame
+    #[serde(default)]
+    pub flood_limit: Option<FloodLimit>,
+    /// Don't suggest similar commands when an unknown command is used
+    #[serde(default)]
+    pub disable_suggestions: bool,
+    /// Channel every audited command is mirrored to
+    #[serde(default)]
+    pub audit_channel: Option<ChannelId>,
+    /// Prefix of text commands, the one from the config if not set
+    #[serde(default)]
+    pub prefix: Option<String>,
+}
+
+/// Limits how many custom commands a single user may use in a time frame
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FloodLimit {
+    pub messages: u32,
+    pub seconds: u64,
+    pub action: FloodAction,
+}
+
+/// What to do when a user exceeds the flood limit
+#[derive(Serialize, Deserialize, poise::ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FloodAction {
+    /// Silently ignore the commands
+    #[name = "ignore"]
+    Ignore,
+    /// React to the first ignored command, then ignore the rest
+    #[name = "react"]
+    React,
+}
+
+impl Record for GuildSettings {
+    const PREFIX: &'static str = "settings";
+    const VERSION: u32 = 1;
+}
+
+/// Kind of record a revision belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RevisionTarget {
+    Command,
+    Alias,
+}
+
+impl RevisionTarget {
+    fn as_str(self) -> &'static str {
+        match self {
+            RevisionTarget::Command => "command",
+            RevisionTarget::Alias => "alias",
+        }
+    }
+}
+
+/// A single change to a custom command or alias
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Revision {
+    /// The response of a command or the target of an alias, `None` if it was removed
+    pub content: Option<String>,
+    /// Kind of the response of a command, text for aliases and revisions made before kinds
+    #[serde(default)]
+    pub kind: ResponseKind,
+    /// User who made the change, unknown for changes made before history was kept
+    pub author: Option<UserId>,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+impl Record for Revision {
+    const PREFIX: &'static str = "revision";
+    const VERSION: u32 = 1;
+}
+
+/// Typed access to the records of a single guild
+pub struct Store {
+    tree: sled::Tree,
+}
+
+impl Store {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    /// Get a record by name
+    pub fn get<R: Record>(&self, name: &str) -> Result<Option<R>, Error> {
+        match self.tree.get(key::<R>(name))? {
+            Some(value) => Ok(Some(decode(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Check whether a record exists
+    pub fn contains<R: Record>(&self, name: &str) -> Result<bool, Error> {
+        Ok(self.tree.contains_key(key::<R>(name))?)
+    }
+
+    /// Insert or replace a record, returning the previous one
+    pub fn insert<R: Record>(&self, name: &str, record: &R) -> Result<Option<R>, Error> {
+        let previous = self.tree.insert(key::<R>(name), encode(record)?)?;
+        previous.map(|value| decode(&value)).transpose()
+    }
+
+    /// Atomically modify an existing record, returning the updated record
+    pub fn update<R: Record>(
+        &self,
+        name: &str,
+        mut f: impl FnMut(&mut R),
+    ) -> Result<Option<R>, Error> {
+        let key = key::<R>(name);
+
+        loop {
+            let current = match self.tree.get(&key)? {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+
+            let mut record: R = decode(&current)?;
+            f(&mut record);
+
+            let swapped =
+                self.tree
+                    .compare_and_swap(&key, Some(current), Some(encode(&record)?))?;
+
+            if swapped.is_ok() {
+                return Ok(Some(record));
+            }
+        }
+    }
+
+    /// Atomically modify a record, starting from its default if it doesn't exist yet,
+    /// returning the updated record
+    pub fn upsert<R: Record + Default>(
+        &self,
+        name: &str,
+        mut f: impl FnMut(&mut R),
+    ) -> Result<R, Error> {
+        let key = key::<R>(name);
+
+        loop {
+            let current = self.tree.get(&key)?;
+
+            let mut record = match &current {
+                Some(value) => decode(value)?,
+                None => R::default(),
+            };
+
+            f(&mut record);
+
+            let swapped = self
+                .tree
+                .compare_and_swap(&key, current, Some(encode(&record)?))?;
+
+            if swapped.is_ok() {
+                return Ok(record);
+            }
+        }
+    }
+
+    /// Remove a record, returning it if it existed
+    pub fn remove<R: Record>(&self, name: &str) -> Result<Option<R>, Error> {
+        let previous = self.tree.remove(key::<R>(name))?;
+        previous.map(|value| decode(&value)).transpose()
+    }
+
+    /// Get the settings of the guild, or the defaults if none were saved
+    pub fn settings(&self) -> Result<GuildSettings, Error> {
+        Ok(self.get(SETTINGS_NAME)?.unwrap_or_default())
+    }
+
+    /// Save the settings of the guild
+    pub fn set_settings(&self, settings: &GuildSettings) -> Result<(), Error> {
+        self.insert(SETTINGS_NAME, settings)?;
+        Ok(())
+    }
+
+    /// Get all revisions of a command or alias with their numbers, oldest first
+    pub fn revisions(
+        &self,
+        target: RevisionTarget,
+        name: &str,
+    ) -> Result<Vec<(u32, Revision)>, Error> {
+        self.scan::<Revision>(&format!("{}/{}/", target.as_str(), name))
+            .map(|entry| {
+                let (key, revision) = entry?;
+                let number = key.rsplit('/').next().unwrap_or_default().parse()?;
+
+                Ok((number, revision))
+            })
+            .collect()
+    }
+
+    /// Append a revision to the history of a command or alias, returning its number
+    pub fn push_revision(
+        &self,
+        target: RevisionTarget,
+        name: &str,
+        revision: &Revision,
+    ) -> Result<u32, Error> {
+        let number = match self.revisions(target, name)?.last() {
+            Some((number, _)) => number + 1,
+            None => 1,
+        };
+
+        // zero padded so revisions are ordered by number
+        let key = format!("{}/{}/{:08}", target.as_str(), name, number);
+        self.insert(&key, revision)?;
+
+        Ok(number)
+    }
+
+    /// Iterate over all records whose name starts with `prefix`, ordered by name
+    pub fn scan<R: Record>(
+        &self,
+        prefix: &str,
+    ) -> impl DoubleEndedIterator<Item = Result<(String, R), Error>> {
+        let skip = R::PREFIX.len() + 1;
+
+        self.tree.scan_prefix(key::<R>(prefix)).map(move |entry| {
+            let (key, value) = entry?;
+            let name = from_utf8(&key)?[skip..].to_string();
+
+            Ok((name, decode(&value)?))
+        })
+    }
+}
+
+fn key<R: Record>(name: &str) -> String {
+    format!("{}:{}", R::PREFIX, name)
+}
+
+fn encode<R: Record>(record: &R) -> Result<Vec<u8>, Error> {
+    Ok(serde_json::to_vec(&Versioned {
+        version: R::VERSION,
+        record,
+    })?)
+}
+
+fn decode<R: Record>(value: &[u8]) -> Result<R, Error> {
+    let versioned: Versioned<serde_json::Value> = serde_json::from_slice(value)?;
+
+    if versioned.version != R::VERSION {
+        bail!(
+            "{} record has version {}, expected {}",
+            R::PREFIX,
+            versioned.version,
+            R::VERSION
+        );
+    }
+
+    Ok(serde_json::from_value(versioned.record)?)
+}
+
+/// Upgrade the database to the current schema version.
+/// Data stored before guilds had their own trees is moved into the legacy guild of the config.
+pub fn migrate(db: &sled::Db, config: &Config) -> Result<(), Error> {
+    let legacy_guild = config.legacy_guild_id;
+    let meta = db.open_tree(META_TREE)?;
+
+    let mut version = match meta.get(SCHEMA_VERSION_KEY)? {
+        Some(value) => from_utf8(&value)?.parse()?,
+        None => 0,
+    };
+
+    if version > SCHEMA_VERSION {
+        bail!(
+            "Database has schema version {}, but this build only supports up to {}",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+
+    // legacy data lives in the default tree, which the first migration empties
+    let legacy_data = version == 0 && !db.is_empty();
+
+    while version < SCHEMA_VERSION {
+        match version {
+            0 => migrate_v1(db, legacy_guild)?,
+            1 if legacy_data => migrate_v2(db, config)?,
+            1 => (),
+            _ => unreachable!(),
+        }
+
+        version += 1;
+        meta.insert(SCHEMA_VERSION_KEY, version.to_string().as_str())?;
+        info!("Migrated database to schema version {}", version);
+    }
+
+    db.flush()?;
+    Ok(())
+}
+
+/// Move legacy data into its guild and convert raw string values into typed records
+fn migrate_v1(db: &sled::Db, legacy_guild: Option<GuildId>) -> Result<(), Error> {
+    if !db.is_empty() {
+        match legacy_guild {
+            Some(guild_id) => {
+                let tree = db.open_tree(format!("guild-{}", guild_id.0))?;
+
+                for entry in db.iter() {
+                    let (key, value) = entry?;
+
+                    tree.insert(&key, value)?;
+                    db.remove(&key)?;
+                }
+            }
+            None => bail!("Found legacy data, set LEGACY_GUILD_ID to migrate it"),
+        }
+    }
+
+    for name in db.tree_names() {
+        if !name.starts_with(b"guild-") {
+            continue;
+        }
+
+        let tree = db.open_tree(&name)?;
+
+        // each entry is converted in one batch, so a crash never loses it
+        // and a rerun skips entries that were converted already
+        let mut batch = sled::Batch::default();
+
+        for entry in tree.iter() {
+            let (raw_key, value) = entry?;
+
+            let (name, text) = match (from_utf8(&raw_key), from_utf8(&value)) {
+                (Ok(name), Ok(text)) => (name, text.to_string()),
+                _ => {
+                    warn!("Dropping entry with invalid UTF-8: {:?}", raw_key);
+                    batch.remove(raw_key);
+                    continue;
+                }
+            };
+
+            if is_record(name, &value) {
+                continue;
+            }
+
+            let (new_key, new_value) = if let Some(name) = name.strip_prefix("alias-") {
+                (key::<Alias>(name), encode(&Alias { command: text })?)
+            } else if let Some(id) = name.strip_prefix("--yawn-") {
+                (
+                    key::<UserProfile>(id),
+                    encode(&UserProfile { yawn: Some(text) })?,
+                )
+            } else {
+                let command = CustomCommand {
+                    response: text,
+                    ..Default::default()
+                };
+
+                (key::<CustomCommand>(name), encode(&command)?)
+            };
+
+            batch.remove(raw_key.clone());
+            batch.insert(new_key.as_bytes(), new_value);
+        }
+
+        tree.apply_batch(batch)?;
+    }
+
+    Ok(())
+}
+
+/// Check whether an entry was written by `migrate_v1` already
+fn is_record(key: &str, value: &[u8]) -> bool {
+    let typed = [CustomCommand::PREFIX, Alias::PREFIX, UserProfile::PREFIX]
+        .iter()
+        .any(|prefix| key.starts_with(&format!("{}:", prefix)));
+
+    typed && serde_json::from_slice::<Versioned<serde_json::Value>>(value).is_ok()
+}
+
+/// Grant the capabilities that used to be hardcoded when upgrading legacy data.
+/// Shell is only granted if its previous holder is still an owner, as it used to require.
+fn migrate_v2(db: &sled::Db, config: &Config) -> Result<(), Error> {
+    // pixelagent007
+    let shell_user = UserId(487247155741065229);
+
+    if config.owners.contains(&shell_user) {
+        let shell = Grants {
+            users: vec![shell_user],
+            roles: Vec::new(),
+        };
+
+        Store::new(db.open_tree(GLOBAL_TREE)?).insert(Capability::Shell.key(), &shell)?;
+    }
+
+    let guild_id = match config.legacy_guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let store = Store::new(db.open_tree(format!("guild-{}", guild_id.0))?);
+
+    // member2, member1, Khaos
+    let yawn = Grants {
+        users: Vec::new(),
+        roles: vec![
+            RoleId(1145786846404677662),
+            RoleId(1126619331007107143),
+            RoleId(1145786499934199932),
+        ],
+    };
+
+    store.insert(Capability::YawnSet.key(), &yawn)?;
+
+    Ok(())
+}
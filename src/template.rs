@@ -0,0 +1,248 @@
+use crate::storage::{Store, Variable};
+use chrono::Utc;
+use log::warn;
+use rand::seq::SliceRandom;
+
+/// Values available to a custom command response when it is rendered
+pub struct Placeholders<'a> {
+    /// Display name of the invoking user
+    pub user: &'a str,
+    /// Mention of the invoking user
+    pub mention: String,
+    /// Mention of the channel the command was used in
+    pub channel: String,
+    /// Name of the guild the command was used in
+    pub guild: &'a str,
+    /// Arguments passed after the command name
+    pub args: Vec<&'a str>,
+    /// Store of the guild, holding variables and counters
+    pub store: &'a Store,
+}
+
+/// Render a custom command response, replacing placeholders with their values.
+/// Supported placeholders:
+/// - `{user}`: the invokers name
+/// - `{mention}`: a mention of the invoker
+/// - `{channel}`: a mention of the current channel
+/// - `{guild}`: the guild name
+/// - `{args}`: all arguments
+/// - `{1}`, `{2}`, ...: a single argument
+/// - `{random:a|b|c}`: a random choice
+/// - `{time}`: the current time
+/// - `{var:name}`: the value of a variable
+/// - `{count:name}`: increment a counter and show its new value, counters are variables too
+///
+/// Arguments can also be referenced shell-style with `$1`, `$2`, ... and `$@`.
+/// `$1` is left as-is when there is no first argument, so prices like `$5` stay intact.
+///
+/// Use `{{` and `}}` for literal braces, and `$$1` or `$$@` for a literal `$1` or `$@`.
+/// Unknown placeholders are left as-is.
+pub fn render(template: &str, values: &Placeholders) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}', '$']) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(argument) = rest.strip_prefix('$') {
+            rest = expand_argument(argument, values, &mut output);
+            continue;
+        }
+
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            output.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+
+        if rest.starts_with('}') {
+            output.push('}');
+            rest = &rest[1..];
+            continue;
+        }
+
+        match rest.find('}') {
+            Some(end) => {
+                let placeholder = &rest[1..end];
+
+                match expand(placeholder, values) {
+                    Some(value) => output.push_str(&value),
+                    None => output.push_str(&rest[..=end]),
+                }
+
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push_str(rest);
+                rest = "";
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Expand a shell-style argument reference following a `$`, returning the remaining template
+fn expand_argument<'t>(template: &'t str, values: &Placeholders, output: &mut String) -> &'t str {
+    if let Some(rest) = template.strip_prefix('@') {
+        output.push_str(&values.args.join(" "));
+        return rest;
+    }
+
+    // only escapes something that would be expanded, so other text with `$$` stays as written
+    if let Some(rest) = template.strip_prefix('$') {
+        if rest.starts_with(|c: char| c == '@' || c.is_ascii_digit()) {
+            output.push('$');
+            output.push_str(&rest[..1]);
+            return &rest[1..];
+        }
+
+        output.push('$');
+        return template;
+    }
+
+    let digits = template
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(template.len());
+
+    let argument = match template[..digits].parse::<usize>() {
+        Ok(index) if index > 0 => values.args.get(index - 1),
+        _ => None,
+    };
+
+    match argument {
+        Some(argument) => {
+            output.push_str(argument);
+            &template[digits..]
+        }
+        _ => {
+            output.push('$');
+            template
+        }
+    }
+}
+
+/// Expand a single placeholder without braces, returning `None` if it is unknown
+fn expand(placeholder: &str, values: &Placeholders) -> Option<String> {
+    let (name, param) = match placeholder.split_once(':') {
+        Some((name, param)) => (name, Some(param)),
+        None => (placeholder, None),
+    };
+
+    match (name.trim(), param) {
+        ("user", None) => Some(values.user.to_string()),
+        ("mention", None) => Some(values.mention.clone()),
+        ("channel", None) => Some(values.channel.clone()),
+        ("guild", None) => Some(values.guild.to_string()),
+        ("args", None) => Some(values.args.join(" ")),
+        ("time", None) => Some(format!("<t:{}:f>", Utc::now().timestamp())),
+        ("random", Some(choices)) => {
+            let choices: Vec<&str> = choices.split('|').collect();
+            choices
+                .choose(&mut rand::thread_rng())
+                .map(|choice| choice.to_string())
+        }
+        ("var", Some(name)) => match values.store.get::<Variable>(&variable_name(name)) {
+            Ok(variable) => variable.map(|variable| variable.value),
+            Err(e) => {
+                warn!("Error getting variable from database: {:?}", e);
+                None
+            }
+        },
+        ("count", Some(name)) => increment(values.store, &variable_name(name)),
+        (index, None) => {
+            let index: usize = index.parse().ok()?;
+            let arg = values.args.get(index.checked_sub(1)?);
+
+            Some(arg.copied().unwrap_or_default().to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Normalize a variable name, so `{var:Version}` and `{var: version}` refer to the same one
+pub fn variable_name(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Increment a counter, returning `None` if it holds something other than a number
+/// or would overflow
+fn increment(store: &Store, name: &str) -> Option<String> {
+    let mut number = None;
+
+    let counted = store.upsert::<Variable>(name, |variable| {
+        let count = match variable.value.trim() {
+            "" => Some(0),
+            value => value.parse::<i64>().ok(),
+        };
+
+        number = count.and_then(|count| count.checked_add(1));
+
+        if let Some(number) = number {
+            variable.value = number.to_string();
+        }
+    });
+
+    match counted {
+        Ok(_) => number.map(|number| number.to_string()),
+        Err(e) => {
+            warn!("Error updating counter in database: {:?}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_with(template: &str, args: &[&str]) -> String {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = Store::new(db.open_tree("test").unwrap());
+
+        let values = Placeholders {
+            user: "user",
+            mention: "<@1>".to_string(),
+            channel: "<#2>".to_string(),
+            guild: "guild",
+            args: args.to_vec(),
+            store: &store,
+        };
+
+        render(template, &values)
+    }
+
+    #[test]
+    fn missing_argument_stays_literal() {
+        assert_eq!(render_with("costs $1", &[]), "costs $1");
+        assert_eq!(render_with("$1 and $2", &["a"]), "a and $2");
+    }
+
+    #[test]
+    fn dollar_escapes() {
+        assert_eq!(render_with("$$1 $$@", &["a"]), "$1 $@");
+        assert_eq!(render_with("$$x $$", &[]), "$$x $$");
+    }
+
+    #[test]
+    fn brace_escapes() {
+        assert_eq!(render_with("{{user}} }}", &[]), "{user} }");
+    }
+
+    #[test]
+    fn unknown_placeholders_stay_literal() {
+        assert_eq!(render_with("{x} {0}", &["a"]), "{x} {0}");
+    }
+
+    #[test]
+    fn arguments() {
+        assert_eq!(render_with("{1}-{args}-$@", &["a", "b"]), "a-a b-a b");
+    }
+
+    #[test]
+    fn empty_random() {
+        assert_eq!(render_with("[{random:}]", &[]), "[]");
+    }
+}
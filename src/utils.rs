@@ -1,7 +1,9 @@
+use crate::storage::{Alias, Store};
+use crate::Context;
+use anyhow::{anyhow, bail, Error};
 use log::{debug, error, warn};
 use std::fmt::Debug;
 use std::process::exit;
-use std::str::from_utf8;
 
 /// Log a message as an error and exit with code 1
 pub fn fatal(message: &str, error: impl Debug) -> ! {
@@ -10,19 +12,66 @@ pub fn fatal(message: &str, error: impl Debug) -> ! {
     exit(1)
 }
 
-/// Get the associated command for an alias
-pub async fn get_alias(tree: &sled::Db, command: String) -> Option<String> {
-    match tree.get(format!("alias-{}", command)) {
-        Ok(Some(val)) => {
-            let alias = from_utf8(val.as_ref()).unwrap_or_else(|e| {
-                warn!("Error converting alias to string: {:?}", e);
-                ""
-            });
+/// Get the store of the guild the command was invoked in
+pub fn guild_store(ctx: Context<'_>) -> Result<Store, Error> {
+    let guild_id = ctx
+        .guild_id()
+        .ok_or_else(|| anyhow!("This command can only be used in a guild"))?;
 
-            Some(alias.to_string())
+    Ok(ctx.data().guild(guild_id)?)
+}
+
+/// Follow a chain of aliases starting at `name`.
+/// The returned chain starts with `name` and ends with the first name that isn't an alias.
+pub fn alias_chain(store: &Store, name: &str) -> Result<Vec<String>, Error> {
+    let mut chain = vec![name.to_string()];
+
+    while let Some(alias) = store.get::<Alias>(chain.last().unwrap())? {
+        if chain.contains(&alias.command) {
+            bail!("Alias cycle: {} -> {}", chain.join(" -> "), alias.command);
+        }
+
+        chain.push(alias.command);
+    }
+
+    Ok(chain)
+}
+
+/// Get the command a name refers to, following aliases.
+/// Names that aren't aliases are returned as-is.
+pub fn resolve_alias(store: &Store, name: &str) -> Result<String, Error> {
+    Ok(alias_chain(store, name)?.pop().unwrap())
+}
+
+/// Get all aliases that eventually resolve to `command`, directly or through other aliases
+pub fn aliases_of(store: &Store, command: &str) -> Result<Vec<String>, Error> {
+    let mut aliases = Vec::new();
+
+    for entry in store.scan::<Alias>("") {
+        let (name, _) = entry?;
+
+        match resolve_alias(store, &name) {
+            Ok(target) if target == command => aliases.push(name),
+            Ok(_) => (),
+            Err(e) => warn!("Error resolving alias {}: {:?}", name, e),
         }
-        Ok(None) => None,
-        Err(_) => None,
+    }
+
+    Ok(aliases)
+}
+
+/// Shorten text to a single line of at most `length` characters, plus an ellipsis if it was cut
+pub fn preview(text: &str, length: usize) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    truncate(&line, length)
+}
+
+/// Shorten text to at most `length` characters, plus an ellipsis if it was cut
+pub fn truncate(text: &str, length: usize) -> String {
+    match text.char_indices().nth(length) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
     }
 }
 
@@ -39,7 +88,7 @@ macro_rules! say {
 }
 
 /// Run a command and send the output to the current context inside a codeblock.  
-/// The command is run inside the directory specified by the `PACKWIZ_REPO_PATH` environment variable.
+/// The command is run inside the pack repository set by `packwiz.repo_path` in the config.
 /// Utilizes `say!` and `send_output` internally.  
 /// Usage: `check_output!(<binary>, <arguments>, <short description of action>, <ctx>);`  
 /// Example usage: `check_output!("git", ["add", "-A"], "add changes to commit", ctx);`  
@@ -62,8 +111,7 @@ macro_rules! check_output {
         )
     };
     ($program:expr, $args:expr, $action:expr, $display_command:expr, $ctx:expr, $silent:expr) => {{
-        let cwd = std::env::var("PACKWIZ_REPO_PATH")
-            .unwrap_or_else(|e| $crate::utils::fatal("PACKWIZ_REPO_PATH not found in env!", e));
+        let cwd = $ctx.data().config().packwiz.repo_path.clone();
 
         match std::process::Command::new($program)
             .args($args)
@@ -71,6 +119,8 @@ macro_rules! check_output {
             .output()
         {
             Ok(output) => {
+                $crate::audit::process_exited($ctx, Some(output.status)).await;
+
                 let mut message = String::new();
 
                 if $silent == false {
@@ -102,7 +152,10 @@ macro_rules! check_output {
 
                 $crate::say!($ctx, "{}", message);
             }
-            Err(_) => $crate::say!($ctx, "Failed to {} - command execution failed.", $action),
+            Err(_) => {
+                $crate::audit::process_exited($ctx, None).await;
+                $crate::say!($ctx, "Failed to {} - command execution failed.", $action)
+            }
         };
     }};
 }